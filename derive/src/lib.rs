@@ -0,0 +1,116 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Error, Expr, ExprLit, Fields, Index, Lit, Member, Result,
+};
+
+/// Implements `channel::GetDataTimeExt` from the field marked with `#[data_time]`
+#[proc_macro_derive(GetDataTime, attributes(data_time))]
+pub fn derive_get_data_time(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+struct DataTimeField {
+    member: Member,
+    with: Option<Expr>,
+    /// The span of the field type, so that a type mismatch points at the field
+    span: proc_macro2::Span,
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2> {
+    let ident = &input.ident;
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let field = find_field(&data.fields, ident.span())?;
+            let member = &field.member;
+            convert(quote_spanned!(field.span=> &self.#member), &field)
+        }
+        Data::Enum(data) => {
+            let mut arms = vec![];
+            for variant in &data.variants {
+                let variant_ident = &variant.ident;
+                let field = find_field(&variant.fields, variant.span())?;
+                let value = convert(quote_spanned!(field.span=> value), &field);
+                let arm = match (&variant.fields, &field.member) {
+                    (Fields::Named(_), Member::Named(name)) => {
+                        quote!(Self::#variant_ident { #name: value, .. } => #value)
+                    }
+                    (_, Member::Unnamed(index)) => {
+                        let skips = (0..index.index).map(|_| quote!(_));
+                        quote!(Self::#variant_ident(#(#skips,)* value, ..) => #value)
+                    }
+                    _ => unreachable!(),
+                };
+                arms.push(arm);
+            }
+            quote!(match self { #(#arms,)* })
+        }
+        Data::Union(data) => {
+            let message = "GetDataTime can not be derived for unions";
+            return Err(Error::new(data.union_token.span, message));
+        }
+    };
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::channel::__private::GetDataTimeExt for #ident #ty_generics
+            #where_clause
+        {
+            fn get_data_time(&self) -> ::channel::__private::NaiveDateTime {
+                #body
+            }
+        }
+    })
+}
+
+fn convert(value: TokenStream2, field: &DataTimeField) -> TokenStream2 {
+    let span = field.span;
+    match &field.with {
+        Some(with) => quote_spanned!(span=> #with(#value)),
+        None => quote_spanned! {span=>
+            <::channel::__private::NaiveDateTime as ::core::clone::Clone>::clone(#value)
+        },
+    }
+}
+
+fn find_field(fields: &Fields, span: proc_macro2::Span) -> Result<DataTimeField> {
+    let mut found = None;
+    for (index, field) in fields.iter().enumerate() {
+        let Some(attr) = field.attrs.iter().find(|a| a.path().is_ident("data_time")) else {
+            continue;
+        };
+        if found.is_some() {
+            return Err(Error::new_spanned(attr, "duplicate #[data_time] field"));
+        }
+        let mut with = None;
+        if !matches!(attr.meta, syn::Meta::Path(_)) {
+            attr.parse_nested_meta(|meta| match meta.path.is_ident("with") {
+                true => {
+                    with = Some(match meta.value()?.parse::<Expr>()? {
+                        Expr::Lit(ExprLit {
+                            lit: Lit::Str(path),
+                            ..
+                        }) => path.parse::<Expr>()?,
+                        expr => expr,
+                    });
+                    Ok(())
+                }
+                false => Err(meta.error("expected `with = path`")),
+            })?;
+        }
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(index)),
+        };
+        found = Some(DataTimeField {
+            member,
+            with,
+            span: field.ty.span(),
+        });
+    }
+    found.ok_or_else(|| Error::new(span, "missing #[data_time] field"))
+}
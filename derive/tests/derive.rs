@@ -0,0 +1,46 @@
+use channel::GetDataTimeExt;
+use chrono::{Duration, NaiveDateTime};
+
+#[derive(Clone, Debug, channel::GetDataTime)]
+struct MyDerivedStruct {
+    #[data_time]
+    time: NaiveDateTime,
+    data: i32,
+}
+
+fn millis_to_data_time(millis: &i64) -> NaiveDateTime {
+    NaiveDateTime::default() + Duration::milliseconds(*millis)
+}
+
+#[derive(Clone, Debug, channel::GetDataTime)]
+struct MyDerivedTuple(i32, #[data_time(with = millis_to_data_time)] i64);
+
+#[derive(Clone, Debug, channel::GetDataTime)]
+enum MyDerivedEnum {
+    Audio {
+        #[data_time]
+        time: NaiveDateTime,
+    },
+    Video(i32, #[data_time(with = "millis_to_data_time")] i64),
+}
+
+#[test]
+fn test_derive_get_data_time() {
+    let now = NaiveDateTime::default() + Duration::days(20000);
+    let derived = MyDerivedStruct {
+        time: now,
+        data: 111,
+    };
+    assert_eq!(derived.get_data_time(), now);
+    let millis_time = NaiveDateTime::default() + Duration::milliseconds(1000);
+    let item = MyDerivedTuple(222, 1000);
+    assert_eq!((item.0, item.get_data_time()), (222, millis_time));
+    assert_eq!(MyDerivedEnum::Audio { time: now }.get_data_time(), now);
+    let item = MyDerivedEnum::Video(333, 1000);
+    assert_eq!(item.get_data_time(), millis_time);
+    assert!(matches!(item, MyDerivedEnum::Video(333, _)));
+
+    let (tx, rx) = channel::new_time_series(None, false, now, 1.0);
+    tx.send(derived);
+    assert_eq!(rx.recv().unwrap().data, 111);
+}
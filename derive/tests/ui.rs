@@ -0,0 +1,8 @@
+// the expected errors contain rustc diagnostics, so they are checked on the toolchain pinned
+// by the derive-ui CI job only
+#[test]
+#[ignore = "run with --ignored on the pinned toolchain"]
+fn test_derive_get_data_time_errors() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
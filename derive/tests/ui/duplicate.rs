@@ -0,0 +1,11 @@
+use chrono::NaiveDateTime;
+
+#[derive(channel::GetDataTime)]
+struct DuplicateDataTime {
+    #[data_time]
+    time: NaiveDateTime,
+    #[data_time]
+    other_time: NaiveDateTime,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use chrono::NaiveDateTime;
+
+#[derive(channel::GetDataTime)]
+struct MissingDataTime {
+    time: NaiveDateTime,
+}
+
+fn main() {}
@@ -0,0 +1,7 @@
+#[derive(channel::GetDataTime)]
+struct WrongDataTimeType {
+    #[data_time]
+    time: i64,
+}
+
+fn main() {}
@@ -1,8 +1,9 @@
 use channel::utils::time_util::NaiveDateTimeExt;
 use chrono::{Duration, NaiveDateTime};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, channel::GetDataTime)]
 struct MyTSStruct {
+    #[data_time]
     time: NaiveDateTime,
     data: i32,
 }
@@ -13,12 +14,6 @@ impl MyTSStruct {
     }
 }
 
-impl channel::GetDataTimeExt for MyTSStruct {
-    fn get_data_time(&self) -> NaiveDateTime {
-        self.time.clone()
-    }
-}
-
 fn main() {
     let (tx, rx) = channel::new_time_series_unbounded_dispatch(NaiveDateTime::now(), 1.0);
     let wrx = rx.weak();
@@ -0,0 +1,411 @@
+use channel::replay::{ReplayFormat, ReplaySource, MAX_BINARY_RECORD_LEN};
+use channel::{GetDataTimeExt, PlaybackClock};
+use chrono::{Duration, NaiveDateTime};
+use serde_json::{Map, Value};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::exit;
+use std::thread::sleep;
+use std::time::Instant;
+
+const USAGE: &str = "Usage: channel-replay <file> [options]
+
+Options:
+  --format <jsonl|csv|bin>  file format, detected from the file extension by default
+  --time-field <name>       name of the data time field or column (default: time)
+  --time-format <format>    chrono format of the csv time column (default: ISO 8601)
+  --speed <speed>           playback speed, must be positive (default: 1.0)
+  --start <time>            skip the records before this data time
+  --end <time>              stop at this data time
+  --loop                    restart from the beginning when the file ends
+  --socket <path>           write to the Unix socket instead of stdout
+  --metrics <seconds>       print the channel metrics to stderr periodically
+
+Binary records are written out unchanged with their length prefix, the data time must be
+the first field of each record";
+
+#[derive(Clone, Debug)]
+enum Payload {
+    Json(Map<String, Value>),
+    Binary(Vec<u8>),
+}
+
+#[derive(Clone, Debug)]
+struct DynRecord {
+    time: NaiveDateTime,
+    payload: Payload,
+}
+
+impl GetDataTimeExt for DynRecord {
+    fn get_data_time(&self) -> NaiveDateTime {
+        self.time
+    }
+}
+
+impl DynRecord {
+    fn write_to(&self, output: &mut dyn Write) -> io::Result<()> {
+        match &self.payload {
+            Payload::Json(value) => {
+                serde_json::to_writer(&mut *output, value)?;
+                output.write_all(b"\n")
+            }
+            Payload::Binary(buf) => {
+                output.write_all(&(buf.len() as u32).to_le_bytes())?;
+                output.write_all(buf)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Options {
+    path: String,
+    format: ReplayFormat,
+    time_field: String,
+    speed: f64,
+    start: Option<NaiveDateTime>,
+    end: Option<NaiveDateTime>,
+    is_loop: bool,
+    socket: Option<String>,
+    metrics_interval: Option<std::time::Duration>,
+}
+
+fn parse_time(value: &str) -> Result<NaiveDateTime, String> {
+    value
+        .parse::<NaiveDateTime>()
+        .map_err(|err| format!("invalid time {value}: {err}"))
+}
+
+fn parse_options<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut args = args.into_iter();
+    let mut path = None;
+    let mut format = None;
+    let mut time_field = "time".to_string();
+    let mut time_format = None;
+    let mut options = Options {
+        path: String::new(),
+        format: ReplayFormat::Jsonl,
+        time_field: String::new(),
+        speed: 1.0,
+        start: None,
+        end: None,
+        is_loop: false,
+        socket: None,
+        metrics_interval: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("missing value of {arg}"));
+        match arg.as_str() {
+            "--format" => format = Some(value()?),
+            "--time-field" => time_field = value()?,
+            "--time-format" => time_format = Some(value()?),
+            "--speed" => {
+                options.speed = value()?.parse().map_err(|_| "invalid speed")?;
+                if !(options.speed.is_finite() && options.speed > 0.0) {
+                    return Err("invalid speed, it must be positive".to_string());
+                }
+            }
+            "--start" => options.start = Some(parse_time(&value()?)?),
+            "--end" => options.end = Some(parse_time(&value()?)?),
+            "--loop" => options.is_loop = true,
+            "--socket" => options.socket = Some(value()?),
+            "--metrics" => {
+                if cfg!(not(feature = "metrics")) {
+                    return Err("--metrics needs the metrics feature".to_string());
+                }
+                let secs: f64 = value()?.parse().map_err(|_| "invalid metrics interval")?;
+                let interval = std::time::Duration::try_from_secs_f64(secs)
+                    .map_err(|_| "invalid metrics interval")?;
+                options.metrics_interval = Some(interval);
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => return Err(format!("unknown argument {arg}\n\n{USAGE}")),
+        }
+    }
+    options.path = path.ok_or_else(|| USAGE.to_string())?;
+    let format = format.unwrap_or_else(|| {
+        let extension = options.path.rsplit_once('.').map_or("", |(_, ext)| ext);
+        match extension {
+            "csv" | "bin" => extension.to_string(),
+            _ => "jsonl".to_string(),
+        }
+    });
+    options.format = match format.as_str() {
+        "jsonl" => ReplayFormat::Jsonl,
+        "csv" => ReplayFormat::Csv {
+            time_column: time_field.clone(),
+            time_format,
+        },
+        "bin" => ReplayFormat::Binary,
+        _ => return Err(format!("unsupported format {format}")),
+    };
+    options.time_field = time_field;
+    Ok(options)
+}
+
+enum Source {
+    Records(ReplaySource<Map<String, Value>>),
+    Binary(BufReader<File>),
+}
+
+impl Source {
+    fn open(options: &Options) -> io::Result<Self> {
+        Ok(match options.format {
+            ReplayFormat::Binary => Source::Binary(BufReader::new(File::open(&options.path)?)),
+            _ => Source::Records(ReplaySource::open(&options.path, options.format.clone())?),
+        })
+    }
+
+    fn next_record(&mut self, time_field: &str) -> io::Result<Option<DynRecord>> {
+        let invalid_data = |err: String| io::Error::new(io::ErrorKind::InvalidData, err);
+        match self {
+            Source::Records(source) => {
+                let Some(value) = source.next_record()? else {
+                    return Ok(None);
+                };
+                let time = match value.get(time_field) {
+                    Some(Value::String(time)) => parse_time(time),
+                    _ => Err(format!("missing time field {time_field}")),
+                }
+                .map_err(invalid_data)?;
+                let payload = Payload::Json(value);
+                Ok(Some(DynRecord { time, payload }))
+            }
+            Source::Binary(reader) => {
+                if reader.fill_buf()?.is_empty() {
+                    return Ok(None);
+                }
+                let mut len_buf = [0u8; 4];
+                reader.read_exact(&mut len_buf)?;
+                let len = u32::from_le_bytes(len_buf) as usize;
+                if len > MAX_BINARY_RECORD_LEN {
+                    return Err(invalid_data(format!(
+                        "binary record of {len} bytes is too long"
+                    )));
+                }
+                let mut buf = vec![0u8; len];
+                reader.read_exact(&mut buf)?;
+                let time =
+                    bincode::deserialize(&buf).map_err(|err| invalid_data(err.to_string()))?;
+                let payload = Payload::Binary(buf);
+                Ok(Some(DynRecord { time, payload }))
+            }
+        }
+    }
+}
+
+struct Player {
+    options: Options,
+    source: Source,
+    pending: Option<DynRecord>,
+}
+
+impl Player {
+    fn new(options: Options) -> io::Result<Self> {
+        let source = Source::open(&options)?;
+        Ok(Self {
+            options,
+            source,
+            pending: None,
+        })
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.source = Source::open(&self.options)?;
+        self.pending = None;
+        Ok(())
+    }
+
+    /// Peek the next record inside the `--start` / `--end` range
+    fn peek(&mut self) -> io::Result<Option<&DynRecord>> {
+        while self.pending.is_none() {
+            let Some(record) = self.source.next_record(&self.options.time_field)? else {
+                return Ok(None);
+            };
+            if self.options.end.is_some_and(|end| record.time > end) {
+                return Ok(None);
+            }
+            if self
+                .options
+                .start
+                .map_or(true, |start| record.time >= start)
+            {
+                self.pending = Some(record);
+            }
+        }
+        Ok(self.pending.as_ref())
+    }
+}
+
+fn run(options: Options, output: &mut dyn Write) -> io::Result<()> {
+    let metrics_interval = options.metrics_interval;
+    let speed = options.speed;
+    let mut player = Player::new(options)?;
+    let Some(start_time) = player.peek()?.map(|record| record.time) else {
+        return Ok(());
+    };
+    let clock = PlaybackClock::new(start_time, speed);
+    let (tx, rx) = channel::new_time_series_with_clock(None, false, &clock);
+    #[cfg(feature = "metrics")]
+    let mut ox = rx.get_observer();
+    let mut last_metrics = Instant::now();
+    let lookahead = Duration::milliseconds(100);
+    loop {
+        // keep the records within the lookahead in the channel, and at least the next one so
+        // that the channel knows when it is due
+        let until = clock.position() + lookahead;
+        while let Some(record) = player.peek()? {
+            if record.time > until && !rx.is_empty() {
+                break;
+            }
+            tx.send(player.pending.take().unwrap());
+        }
+        while let Some(record) = rx.recv() {
+            record.write_to(output)?;
+        }
+        output.flush()?;
+        let mut wait = rx.next_due_in().unwrap_or(std::time::Duration::ZERO);
+        if let Some(interval) = metrics_interval {
+            if last_metrics.elapsed() >= interval {
+                last_metrics = Instant::now();
+                #[cfg(feature = "metrics")]
+                eprintln!("{:?}", ox.get_metrics_result(true));
+            }
+            wait = wait.min(interval.saturating_sub(last_metrics.elapsed()));
+        }
+        if rx.is_empty() && player.peek()?.is_none() {
+            if !player.options.is_loop {
+                return Ok(());
+            }
+            player.rewind()?;
+            clock.seek(start_time);
+            continue;
+        }
+        sleep(wait);
+    }
+}
+
+fn main() {
+    let options = match parse_options(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{err}");
+            exit(2);
+        }
+    };
+    let output: io::Result<Box<dyn Write>> = match &options.socket {
+        #[cfg(unix)]
+        Some(path) => std::os::unix::net::UnixStream::connect(path)
+            .map(|stream| Box::new(stream) as Box<dyn Write>),
+        #[cfg(not(unix))]
+        Some(_) => Err(io::Error::other("unix sockets are not supported")),
+        None => Ok(Box::new(io::stdout())),
+    };
+    if let Err(err) = output.and_then(|mut output| run(options, &mut output)) {
+        eprintln!("channel-replay: {err}");
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use channel::replay::Recorder;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("channel_replay_bin_{name}"));
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = parse_options(args(&[
+            "data.csv",
+            "--time-field",
+            "ts",
+            "--speed",
+            "2.5",
+            "--start",
+            "2024-01-01T00:00:00",
+            "--loop",
+        ]))
+        .unwrap();
+        assert_eq!(options.path, "data.csv");
+        assert_eq!(options.speed, 2.5);
+        assert!(options.is_loop);
+        assert!(options.start.is_some() && options.end.is_none());
+        assert!(
+            matches!(options.format, ReplayFormat::Csv { time_column, .. } if time_column == "ts")
+        );
+        let options = parse_options(args(&["data.bin"])).unwrap();
+        assert!(matches!(options.format, ReplayFormat::Binary));
+        let options = parse_options(args(&["data.txt", "--format", "jsonl"])).unwrap();
+        assert!(matches!(options.format, ReplayFormat::Jsonl));
+
+        assert!(parse_options(args(&[])).is_err());
+        assert!(parse_options(args(&["data.jsonl", "--speed"])).is_err());
+        assert!(parse_options(args(&["data.jsonl", "--speed", "-1"])).is_err());
+        assert!(parse_options(args(&["data.jsonl", "--start", "yesterday"])).is_err());
+        assert!(parse_options(args(&["data.jsonl", "--format", "xml"])).is_err());
+        assert!(parse_options(args(&["data.jsonl", "--unknown"])).is_err());
+        let metrics = parse_options(args(&["data.jsonl", "--metrics", "5"]));
+        assert_eq!(metrics.is_ok(), cfg!(feature = "metrics"));
+    }
+
+    #[test]
+    fn test_replay_jsonl() {
+        let path = temp_path("replay.jsonl");
+        let lines = [
+            r#"{"data":1,"time":"2024-01-01T00:00:00"}"#,
+            r#"{"data":2,"time":"2024-01-01T00:00:00.010"}"#,
+            r#"{"data":3,"time":"2024-01-01T00:00:00.020"}"#,
+            r#"{"data":4,"time":"2024-01-01T00:00:00.030"}"#,
+        ];
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        let options = parse_options(args(&[
+            &path,
+            "--start",
+            "2024-01-01T00:00:00.005",
+            "--end",
+            "2024-01-01T00:00:00.025",
+        ]))
+        .unwrap();
+        let mut output = vec![];
+        let start = Instant::now();
+        run(options, &mut output).unwrap();
+        assert!(start.elapsed() >= std::time::Duration::from_millis(10));
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!("{}\n{}\n", lines[1], lines[2])
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_replay_binary() {
+        #[derive(serde::Serialize)]
+        struct Record {
+            time: NaiveDateTime,
+            data: i32,
+        }
+        let path = temp_path("replay.bin");
+        let time = parse_time("2024-01-01T00:00:00").unwrap();
+        {
+            let mut recorder = Recorder::create(&path, ReplayFormat::Binary).unwrap();
+            for data in 0..3 {
+                let time = time + Duration::milliseconds(5 * data as i64);
+                recorder.record(&Record { time, data }).unwrap();
+            }
+        }
+        let options = parse_options(args(&[&path, "--speed", "2"])).unwrap();
+        let mut output = vec![];
+        run(options, &mut output).unwrap();
+        assert_eq!(output, std::fs::read(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
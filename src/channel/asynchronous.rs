@@ -1,34 +1,60 @@
+use super::AnyBuffer;
 use async_sema::Semaphore;
-use std::ptr::NonNull;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use tokio::sync::Notify;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    Timeout,
+    /// Every sender is gone and the channel is empty
+    Disconnected,
+}
+
+impl std::fmt::Display for RecvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecvError::Timeout => write!(f, "the receive timed out"),
+            RecvError::Disconnected => write!(f, "every sender is gone"),
+        }
+    }
+}
+
+impl std::error::Error for RecvError {}
 
 pub(crate) struct UnboundedBufferAsync<T> {
+    sender_count: usize,
     buf: Vec<T>,
 }
 
 impl<T> UnboundedBufferAsync<T> {
     pub fn new() -> (UnboundedSenderAsync<T>, UnboundedReceiverAsync<T>) {
-        let buf = NonNull::from(Box::leak(Box::new(Mutex::new(UnboundedBufferAsync {
+        let buf = Arc::new(Mutex::new(UnboundedBufferAsync {
+            sender_count: 1,
             buf: vec![],
-        }))));
+        }));
         let sema = Arc::new(Semaphore::new(0));
-        let sema2 = Arc::clone(&sema);
+        let closed = Arc::new(Notify::new());
         (
-            UnboundedSenderAsync { buf, sema: sema2 },
-            UnboundedReceiverAsync { buf, sema },
+            UnboundedSenderAsync {
+                buf: buf.clone(),
+                sema: sema.clone(),
+                closed: closed.clone(),
+            },
+            UnboundedReceiverAsync { buf, sema, closed },
         )
     }
 }
 
 pub struct UnboundedSenderAsync<T> {
-    buf: NonNull<Mutex<UnboundedBufferAsync<T>>>,
+    buf: Arc<Mutex<UnboundedBufferAsync<T>>>,
     sema: Arc<Semaphore>,
+    closed: Arc<Notify>,
 }
 
 impl<T> UnboundedSenderAsync<T> {
     pub fn send(&self, data: T) {
-        let mut buf = unsafe { self.buf.clone().as_mut().lock().unwrap() };
+        let mut buf = self.buf.lock().unwrap();
         buf.buf.push(data);
         self.sema.add_permits(1);
     }
@@ -36,31 +62,88 @@ impl<T> UnboundedSenderAsync<T> {
 
 impl<T> Clone for UnboundedSenderAsync<T> {
     fn clone(&self) -> Self {
+        self.buf.lock().unwrap().sender_count += 1;
         Self {
             buf: self.buf.clone(),
             sema: self.sema.clone(),
+            closed: self.closed.clone(),
         }
     }
 }
 
-unsafe impl<T> Send for UnboundedSenderAsync<T> {}
+impl<T> Drop for UnboundedSenderAsync<T> {
+    /// The last sender wakes the waiting receivers
+    fn drop(&mut self) {
+        let mut buf = self.buf.lock().unwrap();
+        buf.sender_count -= 1;
+        if buf.sender_count == 0 {
+            self.closed.notify_waiters();
+        }
+    }
+}
 
 pub struct UnboundedReceiverAsync<T> {
-    buf: NonNull<Mutex<UnboundedBufferAsync<T>>>,
+    buf: Arc<Mutex<UnboundedBufferAsync<T>>>,
     sema: Arc<Semaphore>,
+    closed: Arc<Notify>,
 }
 
 impl<T> UnboundedReceiverAsync<T> {
-    pub async fn recv(&self) -> T {
-        self.sema.acquire().await;
-        let mut buf = unsafe { self.buf.clone().as_mut().lock().unwrap() };
-        buf.buf.remove(0)
+    /// `RecvError::Disconnected` once every sender is gone and the channel is empty
+    pub async fn recv(&self) -> Result<T, RecvError> {
+        loop {
+            let closed = self.closed.notified();
+            tokio::pin!(closed);
+            closed.as_mut().enable();
+            if let Some(data) = self.try_recv() {
+                return Ok(data);
+            }
+            if self.buf.lock().unwrap().sender_count == 0 {
+                return Err(RecvError::Disconnected);
+            }
+            tokio::select! {
+                _ = self.sema.acquire() => {
+                    let mut buf = self.buf.lock().unwrap();
+                    return Ok(buf.buf.remove(0));
+                }
+                _ = closed => (),
+            }
+        }
+    }
+
+    pub async fn recv_timeout(&self, dur: Duration) -> Result<T, RecvError> {
+        match tokio::time::timeout(dur, self.recv()).await {
+            Ok(ret) => ret,
+            Err(_) => Err(RecvError::Timeout),
+        }
+    }
+
+    pub fn try_recv(&self) -> Option<T> {
+        if !self.sema.try_acquire() {
+            return None;
+        }
+        let mut buf = self.buf.lock().unwrap();
+        Some(buf.buf.remove(0))
+    }
+
+    /// Wait for the first item, then take the items already sent, at most `n` in total
+    pub async fn recv_many(&self, n: usize) -> Result<Vec<T>, RecvError> {
+        if n == 0 {
+            return Ok(vec![]);
+        }
+        let mut items = vec![self.recv().await?];
+        while items.len() < n && self.sema.try_acquire() {
+            items.push(self.buf.lock().unwrap().buf.remove(0));
+        }
+        Ok(items)
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.lock().unwrap().buf.len()
     }
 
-    pub async fn recv_timeout(&self, dur: Duration) -> T {
-        self.sema.acquire_timeout(dur).await;
-        let mut buf = unsafe { self.buf.clone().as_mut().lock().unwrap() };
-        buf.buf.remove(0)
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 }
 
@@ -69,8 +152,189 @@ impl<T> Clone for UnboundedReceiverAsync<T> {
         Self {
             buf: self.buf.clone(),
             sema: self.sema.clone(),
+            closed: self.closed.clone(),
+        }
+    }
+}
+
+struct NotifyAsync {
+    /// Woken on every send and when the last sender is gone
+    recv: Notify,
+    /// Woken on every receive, for the senders waiting on a full bounded channel
+    send: Notify,
+}
+
+/// The async counterpart of `Channel`, with the same bounded and dispatch modes
+pub(crate) struct ChannelAsync<T> {
+    sender_count: usize,
+    receiver_count: usize,
+    max_receiver_index: usize,
+    bounded: Option<usize>,
+    buf: AnyBuffer<T>,
+}
+
+impl<T> ChannelAsync<T> {
+    pub fn new(bounded: Option<usize>, dispatch: bool) -> (SenderAsync<T>, ReceiverAsync<T>) {
+        let mut buf = AnyBuffer::new(bounded, dispatch);
+        buf.new_receiver(0);
+        let chan = Arc::new(Mutex::new(ChannelAsync {
+            sender_count: 1,
+            receiver_count: 1,
+            max_receiver_index: 1,
+            bounded,
+            buf,
+        }));
+        let notify = Arc::new(NotifyAsync {
+            recv: Notify::new(),
+            send: Notify::new(),
+        });
+        (
+            SenderAsync {
+                chan: chan.clone(),
+                notify: notify.clone(),
+            },
+            ReceiverAsync {
+                chan,
+                notify,
+                index: 0,
+            },
+        )
+    }
+
+    fn is_full(&self) -> bool {
+        self.bounded
+            .is_some_and(|bounded| self.buf.total_len() >= bounded)
+    }
+}
+
+pub struct SenderAsync<T> {
+    chan: Arc<Mutex<ChannelAsync<T>>>,
+    notify: Arc<NotifyAsync>,
+}
+
+impl<T: Clone + Sized> SenderAsync<T> {
+    /// Send the item, a full bounded channel drops its oldest item
+    pub fn send(&self, data: T) {
+        let mut chan = self.chan.lock().unwrap();
+        chan.buf.send(data);
+        self.notify.recv.notify_waiters();
+    }
+
+    pub fn send_items(&self, data: Vec<T>) {
+        let mut chan = self.chan.lock().unwrap();
+        chan.buf.send_items(data);
+        self.notify.recv.notify_waiters();
+    }
+
+    /// Send the item, waiting while a bounded channel is full
+    pub async fn send_wait(&self, data: T) {
+        loop {
+            let notified = self.notify.send.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            {
+                let mut chan = self.chan.lock().unwrap();
+                if !chan.is_full() || chan.receiver_count == 0 {
+                    chan.buf.send(data);
+                    self.notify.recv.notify_waiters();
+                    return;
+                }
+            }
+            notified.await;
+        }
+    }
+}
+
+impl<T> Clone for SenderAsync<T> {
+    fn clone(&self) -> Self {
+        self.chan.lock().unwrap().sender_count += 1;
+        Self {
+            chan: self.chan.clone(),
+            notify: self.notify.clone(),
+        }
+    }
+}
+
+impl<T> Drop for SenderAsync<T> {
+    fn drop(&mut self) {
+        let mut chan = self.chan.lock().unwrap();
+        chan.sender_count -= 1;
+        if chan.sender_count == 0 {
+            self.notify.recv.notify_waiters();
+        }
+    }
+}
+
+pub struct ReceiverAsync<T> {
+    chan: Arc<Mutex<ChannelAsync<T>>>,
+    notify: Arc<NotifyAsync>,
+    index: usize,
+}
+
+impl<T: Clone + Sized> ReceiverAsync<T> {
+    pub async fn recv(&self) -> Result<T, RecvError> {
+        loop {
+            let notified = self.notify.recv.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if let Some(data) = self.try_recv() {
+                return Ok(data);
+            }
+            if self.chan.lock().unwrap().sender_count == 0 {
+                return Err(RecvError::Disconnected);
+            }
+            notified.await;
+        }
+    }
+
+    pub async fn recv_timeout(&self, dur: Duration) -> Result<T, RecvError> {
+        match tokio::time::timeout(dur, self.recv()).await {
+            Ok(ret) => ret,
+            Err(_) => Err(RecvError::Timeout),
+        }
+    }
+
+    pub fn try_recv(&self) -> Option<T> {
+        let mut chan = self.chan.lock().unwrap();
+        let ret = chan.buf.recv(self.index);
+        if ret.is_some() {
+            self.notify.send.notify_waiters();
+        }
+        ret
+    }
+}
+
+impl<T> ReceiverAsync<T> {
+    pub fn len(&self) -> usize {
+        self.chan.lock().unwrap().buf.len(self.index)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Clone for ReceiverAsync<T> {
+    /// A dispatch receiver clone also receives the items still buffered for the other receivers
+    fn clone(&self) -> Self {
+        let mut chan = self.chan.lock().unwrap();
+        chan.receiver_count += 1;
+        let index = chan.max_receiver_index;
+        chan.max_receiver_index += 1;
+        chan.buf.new_receiver(index);
+        Self {
+            chan: self.chan.clone(),
+            notify: self.notify.clone(),
+            index,
         }
     }
 }
 
-unsafe impl<T> Send for UnboundedReceiverAsync<T> {}
+impl<T> Drop for ReceiverAsync<T> {
+    fn drop(&mut self) {
+        let mut chan = self.chan.lock().unwrap();
+        chan.buf.drop_receiver(self.index);
+        chan.receiver_count -= 1;
+        self.notify.send.notify_waiters();
+    }
+}
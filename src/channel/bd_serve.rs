@@ -0,0 +1,190 @@
+use super::bidirectional::{BDError, BDUnbResponderAsync, ReplyHandleAsync};
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::oneshot;
+use tokio::task::{JoinHandle, JoinSet};
+
+#[cfg(feature = "metrics")]
+use {
+    crate::utils::metrics_utils::{LatencyMetrics, LatencyResult},
+    std::panic::Location,
+    std::sync::Mutex,
+    std::time::Instant,
+};
+
+#[cfg(feature = "tower")]
+use tower_service::Service;
+
+/// A worker pool running a handler over the requests of an async bidirectional responder
+pub struct BDServer {
+    shutdown: Option<oneshot::Sender<()>>,
+    task: JoinHandle<()>,
+    #[cfg(feature = "metrics")]
+    metrics: Arc<Mutex<LatencyMetrics>>,
+}
+
+/// Reports the latency of every request served by a `BDServer`
+struct BDServeRecorder {
+    #[cfg(feature = "metrics")]
+    metrics: Arc<Mutex<LatencyMetrics>>,
+}
+
+impl BDServeRecorder {
+    /// Spawn the response into the pool, a response of `None` drops the reply handle
+    fn spawn<T2, Fut>(
+        &self,
+        in_flight: &mut JoinSet<()>,
+        response: Fut,
+        handle: ReplyHandleAsync<T2>,
+    ) where
+        T2: Send + 'static,
+        Fut: Future<Output = Option<T2>> + Send + 'static,
+    {
+        let response = tokio::spawn(response);
+        #[cfg(feature = "metrics")]
+        let (metrics, start) = (self.metrics.clone(), Instant::now());
+        in_flight.spawn(async move {
+            let response = response.await;
+            #[cfg(feature = "metrics")]
+            {
+                let panicked = response.as_ref().is_err_and(|err| err.is_panic());
+                metrics.lock().unwrap().record(start.elapsed(), panicked);
+            }
+            match response {
+                Ok(Some(response)) => handle.reply(response),
+                Err(err) if err.is_panic() => handle.fail(BDError::Panicked),
+                _ => (),
+            }
+        });
+    }
+}
+
+impl BDServer {
+    pub(crate) fn new<T1, T2, F, Fut>(
+        mut responder: BDUnbResponderAsync<T1, T2>,
+        concurrency: usize,
+        handler: F,
+        #[cfg(feature = "metrics")] caller: &'static Location<'static>,
+    ) -> Self
+    where
+        T1: Send + 'static,
+        T2: Send + 'static,
+        F: Fn(T1) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = T2> + Send + 'static,
+    {
+        let concurrency = concurrency.max(1);
+        let handler = Arc::new(handler);
+        Self::start(
+            #[cfg(feature = "metrics")]
+            caller,
+            move |mut shutdown_rx, recorder| async move {
+                let mut in_flight = JoinSet::new();
+                loop {
+                    // the pool takes no request while it is full, shutdown is still raced
+                    let has_slot = in_flight.len() < concurrency;
+                    let (data, handle) = tokio::select! {
+                        biased;
+                        Ok(()) = &mut shutdown_rx => break,
+                        request = responder.take_request(), if has_slot => match request {
+                            Ok(request) => request,
+                            Err(_) => break,
+                        },
+                        Some(_) = in_flight.join_next(), if !in_flight.is_empty() => continue,
+                    };
+                    // call the handler in the spawned task, so that a panic before it returns
+                    // its future fails only this request
+                    let handler = handler.clone();
+                    let response = async move { Some(handler(data).await) };
+                    recorder.spawn(&mut in_flight, response, handle);
+                }
+                drop(responder);
+                while in_flight.join_next().await.is_some() {}
+            },
+        )
+    }
+
+    /// Drive the service with the requests of the responder, waiting for `poll_ready` first
+    #[cfg(feature = "tower")]
+    pub(crate) fn with_service<T1, T2, S>(
+        mut responder: BDUnbResponderAsync<T1, T2>,
+        mut service: S,
+        #[cfg(feature = "metrics")] caller: &'static Location<'static>,
+    ) -> Self
+    where
+        T1: Send + 'static,
+        T2: Send + 'static,
+        S: Service<T1, Response = T2> + Send + 'static,
+        S::Future: Send + 'static,
+    {
+        Self::start(
+            #[cfg(feature = "metrics")]
+            caller,
+            move |mut shutdown_rx, recorder| async move {
+                let mut in_flight = JoinSet::new();
+                loop {
+                    let ready = std::future::poll_fn(|cx| service.poll_ready(cx));
+                    let ready = tokio::select! {
+                        biased;
+                        Ok(()) = &mut shutdown_rx => break,
+                        ready = ready => ready.is_ok(),
+                    };
+                    if !ready {
+                        break;
+                    }
+                    let (data, handle) = tokio::select! {
+                        biased;
+                        Ok(()) = &mut shutdown_rx => break,
+                        request = responder.take_request() => match request {
+                            Ok(request) => request,
+                            Err(_) => break,
+                        },
+                        Some(_) = in_flight.join_next(), if !in_flight.is_empty() => continue,
+                    };
+                    let response = service.call(data);
+                    let response = async move { response.await.ok() };
+                    recorder.spawn(&mut in_flight, response, handle);
+                }
+                drop(responder);
+                while in_flight.join_next().await.is_some() {}
+            },
+        )
+    }
+
+    fn start<R, Fut>(#[cfg(feature = "metrics")] caller: &'static Location<'static>, run: R) -> Self
+    where
+        R: FnOnce(oneshot::Receiver<()>, BDServeRecorder) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        let recorder = BDServeRecorder {
+            #[cfg(feature = "metrics")]
+            metrics: Arc::new(Mutex::new(LatencyMetrics::new(caller))),
+        };
+        #[cfg(feature = "metrics")]
+        let metrics = recorder.metrics.clone();
+        let task = tokio::spawn(run(shutdown_rx, recorder));
+        Self {
+            shutdown: Some(shutdown),
+            task,
+            #[cfg(feature = "metrics")]
+            metrics,
+        }
+    }
+
+    /// Stop taking requests and wait until the requests in flight are replied
+    pub async fn shutdown(mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            _ = shutdown.send(());
+        }
+        _ = (&mut self.task).await;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn get_metrics_result(&self, clear: bool) -> LatencyResult {
+        self.metrics.lock().unwrap().get_result(clear)
+    }
+}
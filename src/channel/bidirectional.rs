@@ -1,219 +1,1303 @@
-use super::asynchronous::*;
 use async_sema::Semaphore;
-use std::collections::HashMap;
-use std::ptr::NonNull;
-use std::sync::Mutex;
+use futures_core::Stream;
+use std::collections::{BTreeMap, HashMap};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc as tokio_mpsc, oneshot, Notify};
+
+#[cfg(feature = "tower")]
+use {std::future::Future, std::task::ready, tower_service::Service};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BDError {
+    NoResponse,
+    Timeout,
+    /// Every responder is gone, or every requester for a waiting async responder
+    Disconnected,
+    Full,
+    Panicked,
+    DeadlineExceeded,
+}
+
+impl std::fmt::Display for BDError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BDError::NoResponse => write!(f, "the request was dropped without a response"),
+            BDError::Timeout => write!(f, "the request timed out"),
+            BDError::Disconnected => write!(f, "the other side of the channel is gone"),
+            BDError::Full => write!(f, "too many outstanding requests"),
+            BDError::Panicked => write!(f, "the handler panicked while serving the request"),
+            BDError::DeadlineExceeded => write!(f, "the deadline of the request passed"),
+        }
+    }
+}
+
+impl std::error::Error for BDError {}
+
+/// Identifies a request sent by `send_request`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestTicket(usize);
+
+/// Identifies a responder, every clone has its own id
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ResponderId(usize);
+
+/// How many responses `request_all` waits for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BDQuorum {
+    All,
+    First(usize),
+}
+
+impl BDQuorum {
+    fn count(&self, responder_count: usize) -> usize {
+        match self {
+            BDQuorum::All => responder_count,
+            BDQuorum::First(count) => (*count).min(responder_count),
+        }
+    }
+}
+
+/// The responses collected by `request_all` and the responders that did not answer
+#[derive(Debug, Clone, PartialEq)]
+pub struct BDGather<T2> {
+    pub responses: Vec<(ResponderId, T2)>,
+    pub missing: Vec<ResponderId>,
+}
+
+impl<T2> BDGather<T2> {
+    fn new(responders: Vec<ResponderId>, responses: Vec<(ResponderId, T2)>) -> Self {
+        let missing = responders
+            .into_iter()
+            .filter(|responder| responses.iter().all(|(id, _)| id != responder))
+            .collect();
+        Self { responses, missing }
+    }
+}
+
+struct BDResponses<T2> {
+    resp_buf: Mutex<HashMap<usize, Result<T2, BDError>>>,
+    resp_cond: Condvar,
+}
+
+impl<T2> BDResponses<T2> {
+    fn new() -> Self {
+        Self {
+            resp_buf: Mutex::new(HashMap::new()),
+            resp_cond: Condvar::new(),
+        }
+    }
+
+    fn insert(&self, token: usize, data: Result<T2, BDError>) {
+        self.resp_buf.lock().unwrap().insert(token, data);
+        self.resp_cond.notify_all();
+    }
+
+    /// Take the first response of the tokens that is ready, removing its token
+    fn take(
+        &self,
+        tokens: &mut Vec<usize>,
+        timeout: Option<Duration>,
+    ) -> Option<(RequestTicket, Result<T2, BDError>)> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut resp_buf = self.resp_buf.lock().unwrap();
+        loop {
+            if let Some(index) = tokens.iter().position(|token| resp_buf.contains_key(token)) {
+                let token = tokens.remove(index);
+                return Some((RequestTicket(token), resp_buf.remove(&token)?));
+            }
+            let now = Instant::now();
+            match deadline {
+                Some(deadline) if deadline > now && !tokens.is_empty() => {
+                    resp_buf = self
+                        .resp_cond
+                        .wait_timeout(resp_buf, deadline - now)
+                        .unwrap()
+                        .0;
+                }
+                _ => return None,
+            }
+        }
+    }
+}
+
+/// Counts the outstanding requests and caps them when the channel is bounded
+struct BDLoad {
+    bounded: Option<usize>,
+    outstanding: Mutex<usize>,
+    outstanding_cond: Condvar,
+    outstanding_notify: Notify,
+    taken: AtomicUsize,
+}
+
+impl BDLoad {
+    fn new(bounded: Option<usize>) -> Arc<Self> {
+        Arc::new(Self {
+            bounded,
+            outstanding: Mutex::new(0),
+            outstanding_cond: Condvar::new(),
+            outstanding_notify: Notify::new(),
+            taken: AtomicUsize::new(0),
+        })
+    }
+
+    fn taken(&self) -> usize {
+        self.taken.load(Ordering::SeqCst)
+    }
+
+    fn try_acquire(self: &Arc<Self>) -> Option<BDSlot> {
+        let mut outstanding = self.outstanding.lock().unwrap();
+        self.acquire_locked(&mut outstanding)
+    }
+
+    /// Block until a slot is free, `None` on timeout
+    fn acquire(self: &Arc<Self>, timeout: Option<Duration>) -> Option<BDSlot> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut outstanding = self.outstanding.lock().unwrap();
+        loop {
+            if let Some(slot) = self.acquire_locked(&mut outstanding) {
+                return Some(slot);
+            }
+            outstanding = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if deadline <= now {
+                        return None;
+                    }
+                    let cond = &self.outstanding_cond;
+                    cond.wait_timeout(outstanding, deadline - now).unwrap().0
+                }
+                None => self.outstanding_cond.wait(outstanding).unwrap(),
+            };
+        }
+    }
+
+    async fn acquire_async(self: &Arc<Self>) -> BDSlot {
+        loop {
+            if let Some(slot) = self.try_acquire() {
+                return slot;
+            }
+            self.outstanding_notify.notified().await;
+        }
+    }
+
+    fn acquire_locked(self: &Arc<Self>, outstanding: &mut usize) -> Option<BDSlot> {
+        if self.bounded.is_some_and(|bounded| *outstanding >= bounded) {
+            return None;
+        }
+        *outstanding += 1;
+        Some(BDSlot { load: self.clone() })
+    }
+}
+
+/// One outstanding request, released when the request is replied or dropped
+struct BDSlot {
+    load: Arc<BDLoad>,
+}
+
+impl Drop for BDSlot {
+    fn drop(&mut self) {
+        *self.load.outstanding.lock().unwrap() -= 1;
+        self.load.outstanding_cond.notify_one();
+        self.load.outstanding_notify.notify_one();
+    }
+}
+
+/// A request taken by a responder, counted as in flight until its reply handle is dropped
+struct BDTaken {
+    slot: Arc<BDSlot>,
+}
+
+impl BDTaken {
+    fn new(slot: Arc<BDSlot>) -> Self {
+        slot.load.taken.fetch_add(1, Ordering::SeqCst);
+        Self { slot }
+    }
+}
+
+impl Drop for BDTaken {
+    fn drop(&mut self) {
+        self.slot.load.taken.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The order of the queued requests, higher priorities first, then the oldest
+#[derive(Debug, Clone, Copy, Default)]
+struct BDSchedule {
+    priority: i32,
+    deadline: Option<Instant>,
+}
+
+/// Remove the expired requests and take the scheduled one of the rest
+fn take_scheduled<R>(
+    req_buf: &mut Vec<R>,
+    schedule: fn(&R) -> BDSchedule,
+    expired: &mut Vec<R>,
+) -> Option<R> {
+    let now = Instant::now();
+    let is_expired = |request: &R| {
+        let deadline = schedule(request).deadline;
+        deadline.is_some_and(|deadline| deadline <= now)
+    };
+    if req_buf.iter().any(is_expired) {
+        let (rest, removed): (Vec<R>, Vec<R>) =
+            req_buf.drain(..).partition(|request| !is_expired(request));
+        *req_buf = rest;
+        expired.extend(removed);
+    }
+    let mut best: Option<(usize, i32)> = None;
+    for (index, request) in req_buf.iter().enumerate() {
+        let priority = schedule(request).priority;
+        if best.map_or(true, |(_, best_priority)| priority > best_priority) {
+            best = Some((index, priority));
+        }
+    }
+    best.map(|(index, _)| req_buf.remove(index))
+}
+
+enum ReplyTarget<T2> {
+    Once {
+        resp_buf: Arc<BDResponses<T2>>,
+        token: usize,
+    },
+    Stream(mpsc::Sender<T2>),
+    Gather {
+        sender: mpsc::Sender<(ResponderId, T2)>,
+        responder: ResponderId,
+    },
+}
+
+impl<T2> ReplyTarget<T2> {
+    /// A response stream just ends, a request sent to all lists the responder as missing
+    fn fail(&self, err: BDError) {
+        if let ReplyTarget::Once { resp_buf, token } = self {
+            resp_buf.insert(*token, Err(err));
+        }
+    }
+}
+
+type BDRequest<T1, T2> = (T1, ReplyTarget<T2>, Arc<BDSlot>, BDSchedule);
 
 pub(crate) struct BDUnbBuffer<T1, T2> {
     next_token: usize,
-    req_buf: Vec<(usize, T1)>,
-    resp_buf: HashMap<usize, T2>,
+    req_buf: Vec<BDRequest<T1, T2>>,
+    resp_buf: Arc<BDResponses<T2>>,
+    load: Arc<BDLoad>,
+    next_responder: usize,
+    /// The requests sent to every responder, queued per responder
+    broadcast_bufs: BTreeMap<usize, Vec<BDRequest<T1, T2>>>,
 }
 
 impl<T1, T2> BDUnbBuffer<T1, T2> {
-    pub fn new() -> (BDUnbRequester<T1, T2>, BDUnbResponder<T1, T2>) {
-        let buf = NonNull::from(Box::leak(Box::new(Mutex::new(BDUnbBuffer {
+    pub fn new(bounded: Option<usize>) -> (BDUnbRequester<T1, T2>, BDUnbResponder<T1, T2>) {
+        let resp_buf = Arc::new(BDResponses::new());
+        let load = BDLoad::new(bounded);
+        let buf = Arc::new(Mutex::new(BDUnbBuffer {
             next_token: 0,
             req_buf: vec![],
-            resp_buf: HashMap::new(),
-        }))));
+            resp_buf: resp_buf.clone(),
+            load: load.clone(),
+            next_responder: 1,
+            broadcast_bufs: BTreeMap::from([(0, vec![])]),
+        }));
         (
             BDUnbRequester::<T1, T2> {
-                buf,
+                buf: buf.clone(),
+                resp_buf,
+                load,
                 cache_tokens: vec![],
             },
             BDUnbResponder::<T1, T2> {
                 buf,
-                cache_tokens: vec![],
+                id: 0,
+                cache_handles: vec![],
             },
         )
     }
 
-    pub fn send_request(&mut self, data: T1) -> usize {
-        let ret = self.next_token;
-        self.req_buf.push((self.next_token, data));
+    fn register_responder(&mut self) -> usize {
+        let id = self.next_responder;
+        self.broadcast_bufs.insert(id, vec![]);
+        self.next_responder += 1;
+        id
+    }
+
+    fn send_request(&mut self, data: T1, slot: BDSlot, schedule: BDSchedule) -> usize {
+        let token = self.next_token;
+        let target = ReplyTarget::Once {
+            resp_buf: self.resp_buf.clone(),
+            token,
+        };
+        match self.broadcast_bufs.is_empty() {
+            true => target.fail(BDError::Disconnected),
+            false => self.req_buf.push((data, target, Arc::new(slot), schedule)),
+        }
         self.next_token += 1;
-        ret
+        token
     }
 
-    pub fn get_response(&mut self, token: usize) -> Option<T2> {
-        self.resp_buf.remove(&token)
+    fn send_request_stream(
+        &mut self,
+        data: T1,
+        slot: BDSlot,
+    ) -> Result<ResponseStream<T2>, BDError> {
+        if self.broadcast_bufs.is_empty() {
+            return Err(BDError::Disconnected);
+        }
+        let (tx, rx) = mpsc::channel();
+        let target = ReplyTarget::Stream(tx);
+        self.req_buf
+            .push((data, target, Arc::new(slot), BDSchedule::default()));
+        Ok(ResponseStream { receiver: rx })
     }
 
-    pub fn take_request(&mut self) -> Option<(usize, T1)> {
-        match self.req_buf.len() > 0 {
-            true => Some(self.req_buf.remove(0)),
-            false => None,
+    fn send_request_all(
+        &mut self,
+        data: T1,
+        slot: BDSlot,
+    ) -> (Vec<ResponderId>, mpsc::Receiver<(ResponderId, T2)>)
+    where
+        T1: Clone,
+    {
+        let (tx, rx) = mpsc::channel();
+        let slot = Arc::new(slot);
+        let mut responders = vec![];
+        for (id, broadcast_buf) in self.broadcast_bufs.iter_mut() {
+            let target = ReplyTarget::Gather {
+                sender: tx.clone(),
+                responder: ResponderId(*id),
+            };
+            broadcast_buf.push((data.clone(), target, slot.clone(), BDSchedule::default()));
+            responders.push(ResponderId(*id));
         }
+        (responders, rx)
     }
 
-    pub fn reply_response(&mut self, token: usize, data: T2) {
-        self.resp_buf.insert(token, data);
+    fn take_request(&mut self, responder: usize) -> Option<(T1, ReplyHandle<T2>)> {
+        let broadcast_buf = self.broadcast_bufs.get_mut(&responder)?;
+        let mut expired = vec![];
+        let request = take_scheduled(broadcast_buf, |request| request.3, &mut expired)
+            .or_else(|| take_scheduled(&mut self.req_buf, |request| request.3, &mut expired));
+        for (_, target, _, _) in expired {
+            target.fail(BDError::DeadlineExceeded);
+        }
+        let (data, target, slot, _) = request?;
+        let handle = ReplyHandle {
+            target,
+            replied: false,
+            _taken: BDTaken::new(slot),
+        };
+        Some((data, handle))
+    }
+
+    /// The queued requests and the taken requests not replied yet
+    fn load(&self) -> (usize, usize) {
+        let broadcast_len: usize = self.broadcast_bufs.values().map(|buf| buf.len()).sum();
+        (self.req_buf.len() + broadcast_len, self.load.taken())
+    }
+}
+
+/// Replies to one request, dropping it without a reply reports `BDError::NoResponse`
+pub struct ReplyHandle<T2> {
+    target: ReplyTarget<T2>,
+    replied: bool,
+    _taken: BDTaken,
+}
+
+impl<T2> ReplyHandle<T2> {
+    /// A streamed request takes any number of responses, a plain request keeps the first
+    pub fn send(&mut self, data: T2) {
+        match &self.target {
+            ReplyTarget::Once { resp_buf, token } if !self.replied => {
+                resp_buf.insert(*token, Ok(data));
+            }
+            ReplyTarget::Gather { sender, responder } if !self.replied => {
+                _ = sender.send((*responder, data));
+            }
+            ReplyTarget::Once { .. } | ReplyTarget::Gather { .. } => (),
+            ReplyTarget::Stream(sender) => _ = sender.send(data),
+        }
+        self.replied = true;
+    }
+
+    pub fn reply(mut self, data: T2) {
+        self.send(data);
+    }
+
+    /// End the response stream
+    pub fn finish(self) {}
+}
+
+impl<T2> Drop for ReplyHandle<T2> {
+    fn drop(&mut self) {
+        if let ReplyTarget::Once { resp_buf, token } = &self.target {
+            if !self.replied {
+                resp_buf.insert(*token, Err(BDError::NoResponse));
+            }
+        }
+    }
+}
+
+/// The responses of a request sent by `request_stream`
+pub struct ResponseStream<T2> {
+    receiver: mpsc::Receiver<T2>,
+}
+
+impl<T2> ResponseStream<T2> {
+    pub fn try_next(&mut self) -> Option<T2> {
+        self.receiver.try_recv().ok()
+    }
+
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<T2> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+}
+
+impl<T2> Iterator for ResponseStream<T2> {
+    type Item = T2;
+
+    fn next(&mut self) -> Option<T2> {
+        self.receiver.recv().ok()
+    }
+}
+
+/// A request sent to every responder by `BDUnbRequester::send_request_all`
+pub struct PendingGather<T2> {
+    responders: Vec<ResponderId>,
+    receiver: mpsc::Receiver<(ResponderId, T2)>,
+}
+
+impl<T2> PendingGather<T2> {
+    pub fn responders(&self) -> &[ResponderId] {
+        &self.responders
+    }
+
+    /// Collect the responses until the quorum is reached or the timeout
+    pub fn collect(self, quorum: BDQuorum, timeout: Duration) -> BDGather<T2> {
+        let deadline = Instant::now() + timeout;
+        let mut responses = vec![];
+        while responses.len() < quorum.count(self.responders.len()) {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.receiver.recv_timeout(timeout) {
+                Ok(response) => responses.push(response),
+                Err(_) => break,
+            }
+        }
+        BDGather::new(self.responders, responses)
     }
 }
 
 pub struct BDUnbRequester<T1, T2> {
-    buf: NonNull<Mutex<BDUnbBuffer<T1, T2>>>,
+    buf: Arc<Mutex<BDUnbBuffer<T1, T2>>>,
+    resp_buf: Arc<BDResponses<T2>>,
+    load: Arc<BDLoad>,
     cache_tokens: Vec<usize>,
 }
 
 impl<T1, T2> BDUnbRequester<T1, T2> {
-    pub fn send_request(&mut self, data: T1) {
-        let mut buf = unsafe { self.buf.clone().as_mut().lock().unwrap() };
-        self.cache_tokens.push(buf.send_request(data));
+    pub fn send_request(&mut self, data: T1) -> RequestTicket {
+        let slot = self.load.acquire(None).unwrap();
+        self.send_request_slot(data, slot, BDSchedule::default())
     }
 
-    pub fn send_requests(&mut self, data: Vec<T1>) {
-        for item in data {
-            self.send_request(item);
-        }
+    /// Higher priorities are served first, the request fails once the deadline passes
+    pub fn send_request_with(
+        &mut self,
+        data: T1,
+        priority: i32,
+        deadline: Option<Instant>,
+    ) -> RequestTicket {
+        let slot = self.load.acquire(None).unwrap();
+        self.send_request_slot(data, slot, BDSchedule { priority, deadline })
     }
 
-    pub fn try_get_response(&mut self) -> Option<T2> {
-        match self.cache_tokens.is_empty() {
-            true => None,
-            false => {
-                let mut buf = unsafe { self.buf.clone().as_mut().lock().unwrap() };
-                match buf.get_response(self.cache_tokens[0]) {
-                    Some(data) => {
-                        self.cache_tokens.remove(0);
-                        Some(data)
-                    }
-                    None => None,
-                }
-            }
+    pub fn try_send_request(&mut self, data: T1) -> Result<RequestTicket, BDError> {
+        let slot = self.load.try_acquire().ok_or(BDError::Full)?;
+        Ok(self.send_request_slot(data, slot, BDSchedule::default()))
+    }
+
+    pub fn send_request_timeout(
+        &mut self,
+        data: T1,
+        timeout: Duration,
+    ) -> Result<RequestTicket, BDError> {
+        let slot = self.load.acquire(Some(timeout)).ok_or(BDError::Timeout)?;
+        Ok(self.send_request_slot(data, slot, BDSchedule::default()))
+    }
+
+    pub fn send_requests(&mut self, data: Vec<T1>) -> Vec<RequestTicket> {
+        data.into_iter()
+            .map(|item| self.send_request(item))
+            .collect()
+    }
+
+    /// Send a request that takes any number of responses
+    pub fn request_stream(&mut self, data: T1) -> Result<ResponseStream<T2>, BDError> {
+        let slot = self.load.acquire(None).unwrap();
+        let mut buf = self.buf.lock().unwrap();
+        buf.send_request_stream(data, slot)
+    }
+
+    /// Send a clone of the request to every responder and collect their responses
+    pub fn request_all(
+        &mut self,
+        data: T1,
+        quorum: BDQuorum,
+        timeout: Duration,
+    ) -> Result<BDGather<T2>, BDError>
+    where
+        T1: Clone,
+    {
+        let deadline = Instant::now() + timeout;
+        let slot = self.load.acquire(Some(timeout)).ok_or(BDError::Timeout)?;
+        let pending = self.send_request_all_with(data, slot)?;
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        Ok(pending.collect(quorum, timeout))
+    }
+
+    /// Send a clone of the request to every responder, collected by `PendingGather::collect`
+    pub fn send_request_all(&mut self, data: T1) -> Result<PendingGather<T2>, BDError>
+    where
+        T1: Clone,
+    {
+        let slot = self.load.acquire(None).unwrap();
+        self.send_request_all_with(data, slot)
+    }
+
+    pub fn queue_len(&self) -> usize {
+        let buf = self.buf.lock().unwrap();
+        buf.load().0
+    }
+
+    pub fn in_flight(&self) -> usize {
+        let buf = self.buf.lock().unwrap();
+        buf.load().1
+    }
+
+    pub fn try_get_response(&mut self) -> Option<Result<T2, BDError>> {
+        let mut tokens = self.cache_tokens.first().cloned().into_iter().collect();
+        let (ticket, data) = self.resp_buf.take(&mut tokens, None)?;
+        self.cache_tokens.retain(|token| *token != ticket.0);
+        Some(data)
+    }
+
+    pub fn try_get_response_for(&mut self, ticket: RequestTicket) -> Option<Result<T2, BDError>> {
+        self.get_response_for_impl(ticket, None)
+    }
+
+    pub fn try_get_any_response(&mut self) -> Option<(RequestTicket, Result<T2, BDError>)> {
+        self.resp_buf.take(&mut self.cache_tokens, None)
+    }
+
+    pub fn get_response_for(
+        &mut self,
+        ticket: RequestTicket,
+        timeout: Duration,
+    ) -> Option<Result<T2, BDError>> {
+        self.get_response_for_impl(ticket, Some(timeout))
+    }
+
+    pub fn get_any_response(
+        &mut self,
+        timeout: Duration,
+    ) -> Option<(RequestTicket, Result<T2, BDError>)> {
+        self.resp_buf.take(&mut self.cache_tokens, Some(timeout))
+    }
+
+    fn get_response_for_impl(
+        &mut self,
+        ticket: RequestTicket,
+        timeout: Option<Duration>,
+    ) -> Option<Result<T2, BDError>> {
+        let mut tokens = match self.cache_tokens.contains(&ticket.0) {
+            true => vec![ticket.0],
+            false => vec![],
+        };
+        let (_, data) = self.resp_buf.take(&mut tokens, timeout)?;
+        self.cache_tokens.retain(|token| *token != ticket.0);
+        Some(data)
+    }
+
+    fn send_request_all_with(
+        &mut self,
+        data: T1,
+        slot: BDSlot,
+    ) -> Result<PendingGather<T2>, BDError>
+    where
+        T1: Clone,
+    {
+        let mut buf = self.buf.lock().unwrap();
+        let (responders, receiver) = buf.send_request_all(data, slot);
+        match responders.is_empty() {
+            true => Err(BDError::Disconnected),
+            false => Ok(PendingGather {
+                responders,
+                receiver,
+            }),
         }
     }
+
+    fn send_request_slot(&mut self, data: T1, slot: BDSlot, schedule: BDSchedule) -> RequestTicket {
+        let mut buf = self.buf.lock().unwrap();
+        let token = buf.send_request(data, slot, schedule);
+        self.cache_tokens.push(token);
+        RequestTicket(token)
+    }
 }
 
 impl<T1, T2> Clone for BDUnbRequester<T1, T2> {
     fn clone(&self) -> Self {
         Self {
             buf: self.buf.clone(),
+            resp_buf: self.resp_buf.clone(),
+            load: self.load.clone(),
             cache_tokens: vec![],
         }
     }
 }
 
 pub struct BDUnbResponder<T1, T2> {
-    buf: NonNull<Mutex<BDUnbBuffer<T1, T2>>>,
-    cache_tokens: Vec<usize>,
+    buf: Arc<Mutex<BDUnbBuffer<T1, T2>>>,
+    id: usize,
+    cache_handles: Vec<ReplyHandle<T2>>,
 }
 
 impl<T1, T2> BDUnbResponder<T1, T2> {
+    pub fn id(&self) -> ResponderId {
+        ResponderId(self.id)
+    }
+
+    /// The requests sent to every responder come first
+    pub fn take_request(&mut self) -> Option<(T1, ReplyHandle<T2>)> {
+        let mut buf = self.buf.lock().unwrap();
+        buf.take_request(self.id)
+    }
+
+    /// The request is replied by `reply_response` in the order taken
     pub fn try_take_request(&mut self) -> Option<T1> {
-        let mut buf = unsafe { self.buf.clone().as_mut().lock().unwrap() };
-        match buf.take_request() {
-            Some((token, data)) => {
-                self.cache_tokens.push(token);
-                Some(data)
-            }
-            None => None,
-        }
+        let (data, handle) = self.take_request()?;
+        self.cache_handles.push(handle);
+        Some(data)
     }
 
     pub fn reply_response(&mut self, data: T2) {
-        let mut buf = unsafe { self.buf.clone().as_mut().lock().unwrap() };
-        let token = self.cache_tokens.remove(0);
-        buf.reply_response(token, data)
+        self.cache_handles.remove(0).reply(data);
+    }
+
+    pub fn queue_len(&self) -> usize {
+        let buf = self.buf.lock().unwrap();
+        buf.load().0
+    }
+
+    pub fn in_flight(&self) -> usize {
+        let buf = self.buf.lock().unwrap();
+        buf.load().1
     }
 }
 
 impl<T1, T2> Clone for BDUnbResponder<T1, T2> {
     fn clone(&self) -> Self {
+        let mut buf = self.buf.lock().unwrap();
         Self {
             buf: self.buf.clone(),
-            cache_tokens: vec![],
+            id: buf.register_responder(),
+            cache_handles: vec![],
+        }
+    }
+}
+
+impl<T1, T2> Drop for BDUnbResponder<T1, T2> {
+    fn drop(&mut self) {
+        let mut buf = self.buf.lock().unwrap();
+        buf.broadcast_bufs.remove(&self.id);
+        if buf.broadcast_bufs.is_empty() {
+            for (_, target, _, _) in buf.req_buf.drain(..) {
+                target.fail(BDError::Disconnected);
+            }
+        }
+    }
+}
+
+enum ReplySenderAsync<T2> {
+    Once(Option<oneshot::Sender<Result<T2, BDError>>>),
+    Stream(tokio_mpsc::UnboundedSender<T2>),
+    Gather {
+        sender: Option<tokio_mpsc::UnboundedSender<(ResponderId, T2)>>,
+        responder: ResponderId,
+    },
+}
+
+impl<T2> ReplySenderAsync<T2> {
+    fn fail(&mut self, err: BDError) {
+        if let ReplySenderAsync::Once(sender) = self {
+            if let Some(sender) = sender.take() {
+                _ = sender.send(Err(err));
+            }
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        match self {
+            ReplySenderAsync::Once(sender) => sender.as_ref().map_or(true, |s| s.is_closed()),
+            ReplySenderAsync::Stream(sender) => sender.is_closed(),
+            ReplySenderAsync::Gather { sender, .. } => {
+                sender.as_ref().map_or(true, |s| s.is_closed())
+            }
+        }
+    }
+}
+
+type BDReceiverAsync<T2> = oneshot::Receiver<Result<T2, BDError>>;
+type BDRequestAsync<T1, T2> = (T1, ReplySenderAsync<T2>, Arc<BDSlot>, BDSchedule);
+
+/// The requests sent to every responder, queued for one async responder
+struct BDBroadcastAsync<T1, T2> {
+    req_buf: Vec<BDRequestAsync<T1, T2>>,
+    notify: Arc<Notify>,
+}
+
+impl<T1, T2> BDBroadcastAsync<T1, T2> {
+    fn new() -> Self {
+        Self {
+            req_buf: vec![],
+            notify: Arc::new(Notify::new()),
         }
     }
 }
 
 pub(crate) struct BDUnbBufferAsync<T1, T2> {
-    req_buf: Vec<(T1, UnboundedSenderAsync<T2>)>,
+    req_buf: Vec<BDRequestAsync<T1, T2>>,
+    load: Arc<BDLoad>,
+    requester_count: usize,
+    next_responder: usize,
+    responders: BTreeMap<usize, BDBroadcastAsync<T1, T2>>,
 }
 
 impl<T1, T2> BDUnbBufferAsync<T1, T2> {
-    pub fn new() -> (BDUnbRequesterAsync<T1, T2>, BDUnbResponderAsync<T1, T2>) {
-        let buf = NonNull::from(Box::leak(Box::new(Mutex::new(BDUnbBufferAsync {
+    pub fn new(
+        bounded: Option<usize>,
+    ) -> (BDUnbRequesterAsync<T1, T2>, BDUnbResponderAsync<T1, T2>) {
+        let load = BDLoad::new(bounded);
+        let broadcast = BDBroadcastAsync::new();
+        let notify = broadcast.notify.clone();
+        let buf = Arc::new(Mutex::new(BDUnbBufferAsync {
             req_buf: vec![],
-        }))));
+            load: load.clone(),
+            requester_count: 1,
+            next_responder: 1,
+            responders: BTreeMap::from([(0, broadcast)]),
+        }));
         let sema = Semaphore::new(0);
         (
             BDUnbRequesterAsync::<T1, T2> {
-                buf,
+                buf: buf.clone(),
                 sema: sema.clone(),
+                load,
+                #[cfg(feature = "tower")]
+                ready_slot: None,
+                #[cfg(feature = "tower")]
+                acquiring: None,
+            },
+            BDUnbResponderAsync::<T1, T2> {
+                buf,
+                sema,
+                id: 0,
+                notify,
             },
-            BDUnbResponderAsync::<T1, T2> { buf, sema },
         )
     }
 
-    pub fn request(&mut self, data: T1) -> UnboundedReceiverAsync<T2> {
-        let (tx, rx) = UnboundedBufferAsync::new();
-        self.req_buf.push((data, tx));
-        rx
+    /// The error of a request whose reply handle is dropped without a response
+    fn no_response_error(&self) -> BDError {
+        match self.responders.is_empty() {
+            true => BDError::Disconnected,
+            false => BDError::NoResponse,
+        }
+    }
+
+    fn register_responder(&mut self) -> (usize, Arc<Notify>) {
+        let id = self.next_responder;
+        let broadcast = BDBroadcastAsync::new();
+        let notify = broadcast.notify.clone();
+        self.responders.insert(id, broadcast);
+        self.next_responder += 1;
+        (id, notify)
+    }
+
+    fn request(
+        &mut self,
+        data: T1,
+        slot: BDSlot,
+        schedule: BDSchedule,
+    ) -> Result<BDReceiverAsync<T2>, BDError> {
+        if self.responders.is_empty() {
+            return Err(BDError::Disconnected);
+        }
+        let (tx, rx) = oneshot::channel();
+        let sender = ReplySenderAsync::Once(Some(tx));
+        self.req_buf.push((data, sender, Arc::new(slot), schedule));
+        Ok(rx)
+    }
+
+    fn request_stream(
+        &mut self,
+        data: T1,
+        slot: BDSlot,
+    ) -> Result<ResponseStreamAsync<T2>, BDError> {
+        if self.responders.is_empty() {
+            return Err(BDError::Disconnected);
+        }
+        let (tx, rx) = tokio_mpsc::unbounded_channel();
+        let sender = ReplySenderAsync::Stream(tx);
+        self.req_buf
+            .push((data, sender, Arc::new(slot), BDSchedule::default()));
+        Ok(ResponseStreamAsync { receiver: rx })
+    }
+
+    fn request_all(
+        &mut self,
+        data: T1,
+        slot: BDSlot,
+    ) -> (
+        Vec<ResponderId>,
+        tokio_mpsc::UnboundedReceiver<(ResponderId, T2)>,
+    )
+    where
+        T1: Clone,
+    {
+        let (tx, rx) = tokio_mpsc::unbounded_channel();
+        let slot = Arc::new(slot);
+        let mut responders = vec![];
+        for (id, broadcast) in self.responders.iter_mut() {
+            let sender = ReplySenderAsync::Gather {
+                sender: Some(tx.clone()),
+                responder: ResponderId(*id),
+            };
+            let schedule = BDSchedule::default();
+            broadcast
+                .req_buf
+                .push((data.clone(), sender, slot.clone(), schedule));
+            broadcast.notify.notify_one();
+            responders.push(ResponderId(*id));
+        }
+        (responders, rx)
+    }
+
+    /// `None` means the permit belonged to a cancelled or expired request
+    pub fn take_request(&mut self) -> Option<(T1, ReplyHandleAsync<T2>)> {
+        let mut expired = vec![];
+        let request = take_scheduled(&mut self.req_buf, |request| request.3, &mut expired);
+        for (_, mut sender, _, _) in expired {
+            sender.fail(BDError::DeadlineExceeded);
+        }
+        Self::accept_request(request?)
+    }
+
+    fn take_broadcast(&mut self, responder: usize) -> Option<(T1, ReplyHandleAsync<T2>)> {
+        let broadcast = self.responders.get_mut(&responder)?;
+        while broadcast.req_buf.len() > 0 {
+            let request = broadcast.req_buf.remove(0);
+            if let Some(request) = Self::accept_request(request) {
+                return Some(request);
+            }
+        }
+        None
+    }
+
+    fn accept_request(request: BDRequestAsync<T1, T2>) -> Option<(T1, ReplyHandleAsync<T2>)> {
+        let (data, sender, slot, _) = request;
+        match sender.is_closed() {
+            true => None,
+            false => Some((
+                data,
+                ReplyHandleAsync {
+                    sender,
+                    _taken: BDTaken::new(slot),
+                },
+            )),
+        }
+    }
+
+    /// Every requester is gone and no request is left for the responders
+    fn is_disconnected(&self) -> bool {
+        self.requester_count == 0 && self.req_buf.is_empty()
     }
 
-    pub async fn take_request(&mut self) -> (T1, UnboundedSenderAsync<T2>) {
-        self.req_buf.remove(0)
+    fn load(&self) -> (usize, usize) {
+        let broadcast_len: usize = self.responders.values().map(|b| b.req_buf.len()).sum();
+        (self.req_buf.len() + broadcast_len, self.load.taken())
     }
 }
 
+/// Replies to one request, the request is cancelled once the requester drops its future
+pub struct ReplyHandleAsync<T2> {
+    sender: ReplySenderAsync<T2>,
+    _taken: BDTaken,
+}
+
+impl<T2> ReplyHandleAsync<T2> {
+    pub fn send(&mut self, data: T2) {
+        match &mut self.sender {
+            ReplySenderAsync::Once(sender) => {
+                if let Some(sender) = sender.take() {
+                    _ = sender.send(Ok(data));
+                }
+            }
+            ReplySenderAsync::Stream(sender) => _ = sender.send(data),
+            ReplySenderAsync::Gather { sender, responder } => {
+                if let Some(sender) = sender.take() {
+                    _ = sender.send((*responder, data));
+                }
+            }
+        }
+    }
+
+    pub fn reply(mut self, data: T2) {
+        self.send(data);
+    }
+
+    pub fn finish(self) {}
+
+    pub(crate) fn fail(mut self, err: BDError) {
+        self.sender.fail(err);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    /// Wait until the request is cancelled
+    pub async fn cancelled(&mut self) {
+        match &mut self.sender {
+            ReplySenderAsync::Once(Some(sender)) => sender.closed().await,
+            ReplySenderAsync::Once(None) => (),
+            ReplySenderAsync::Stream(sender) => sender.closed().await,
+            ReplySenderAsync::Gather {
+                sender: Some(sender),
+                ..
+            } => sender.closed().await,
+            ReplySenderAsync::Gather { sender: None, .. } => (),
+        }
+    }
+}
+
+/// Dropping the stream cancels the request
+pub struct ResponseStreamAsync<T2> {
+    receiver: tokio_mpsc::UnboundedReceiver<T2>,
+}
+
+impl<T2> ResponseStreamAsync<T2> {
+    pub async fn next(&mut self) -> Option<T2> {
+        self.receiver.recv().await
+    }
+
+    pub fn try_next(&mut self) -> Option<T2> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl<T2> Stream for ResponseStreamAsync<T2> {
+    type Item = T2;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T2>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+#[cfg(feature = "tower")]
+type BDSlotFuture = Pin<Box<dyn Future<Output = BDSlot> + Send + Sync>>;
+
 pub struct BDUnbRequesterAsync<T1, T2> {
-    buf: NonNull<Mutex<BDUnbBufferAsync<T1, T2>>>,
+    buf: Arc<Mutex<BDUnbBufferAsync<T1, T2>>>,
     sema: Semaphore,
+    load: Arc<BDLoad>,
+    /// The slot reserved by `poll_ready` for the next `call`
+    #[cfg(feature = "tower")]
+    ready_slot: Option<BDSlot>,
+    #[cfg(feature = "tower")]
+    acquiring: Option<BDSlotFuture>,
 }
 
 impl<T1, T2> Clone for BDUnbRequesterAsync<T1, T2> {
     fn clone(&self) -> Self {
+        self.buf.lock().unwrap().requester_count += 1;
         Self {
             buf: self.buf.clone(),
             sema: self.sema.clone(),
+            load: self.load.clone(),
+            #[cfg(feature = "tower")]
+            ready_slot: None,
+            #[cfg(feature = "tower")]
+            acquiring: None,
         }
     }
 }
 
 impl<T1, T2> BDUnbRequesterAsync<T1, T2> {
-    pub async fn request(&mut self, data: T1) -> T2 {
-        let receiver = {
-            let mut buf = unsafe { self.buf.clone().as_mut().lock().unwrap() };
-            buf.request(data)
+    /// Dropping the future cancels the request
+    pub async fn request(&mut self, data: T1) -> Result<T2, BDError> {
+        let slot = self.load.acquire_async().await;
+        let receiver = self.send_request(data, slot)?;
+        receiver
+            .await
+            .unwrap_or_else(|_| Err(self.no_response_error()))
+    }
+
+    /// Like `request`, a request past its deadline is cancelled even once taken
+    pub async fn request_with(
+        &mut self,
+        data: T1,
+        priority: i32,
+        deadline: Option<Instant>,
+    ) -> Result<T2, BDError> {
+        let response = async {
+            let slot = self.load.acquire_async().await;
+            let schedule = BDSchedule { priority, deadline };
+            let receiver = self.send_request_slot(data, slot, schedule)?;
+            receiver
+                .await
+                .unwrap_or_else(|_| Err(self.no_response_error()))
+        };
+        let Some(deadline) = deadline else {
+            return response.await;
+        };
+        let deadline = tokio::time::Instant::from_std(deadline);
+        match tokio::time::timeout_at(deadline, response).await {
+            Ok(ret) => ret,
+            Err(_) => Err(BDError::DeadlineExceeded),
+        }
+    }
+
+    pub async fn try_request(&mut self, data: T1) -> Result<T2, BDError> {
+        let slot = self.load.try_acquire().ok_or(BDError::Full)?;
+        let receiver = self.send_request(data, slot)?;
+        receiver
+            .await
+            .unwrap_or_else(|_| Err(self.no_response_error()))
+    }
+
+    /// The timeout covers both waiting for room in a bounded channel and waiting for the response
+    pub async fn request_timeout(&mut self, data: T1, timeout: Duration) -> Result<T2, BDError> {
+        let response = async {
+            let slot = self.load.acquire_async().await;
+            let receiver = self.send_request(data, slot)?;
+            receiver
+                .await
+                .unwrap_or_else(|_| Err(self.no_response_error()))
+        };
+        match tokio::time::timeout(timeout, response).await {
+            Ok(ret) => ret,
+            Err(_) => Err(BDError::Timeout),
+        }
+    }
+
+    pub async fn request_stream(&mut self, data: T1) -> Result<ResponseStreamAsync<T2>, BDError> {
+        let slot = self.load.acquire_async().await;
+        let mut buf = self.buf.lock().unwrap();
+        let stream = buf.request_stream(data, slot)?;
+        self.sema.add_permits(1);
+        Ok(stream)
+    }
+
+    pub async fn request_all(
+        &mut self,
+        data: T1,
+        quorum: BDQuorum,
+        timeout: Duration,
+    ) -> Result<BDGather<T2>, BDError>
+    where
+        T1: Clone,
+    {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let slot = tokio::time::timeout_at(deadline, self.load.acquire_async())
+            .await
+            .map_err(|_| BDError::Timeout)?;
+        let (responders, mut receiver) = {
+            let mut buf = self.buf.lock().unwrap();
+            buf.request_all(data, slot)
         };
-        receiver.recv().await
+        if responders.is_empty() {
+            return Err(BDError::Disconnected);
+        }
+        let mut responses = vec![];
+        while responses.len() < quorum.count(responders.len()) {
+            match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                Ok(Some(response)) => responses.push(response),
+                _ => break,
+            }
+        }
+        Ok(BDGather::new(responders, responses))
+    }
+
+    /// Cancelled requests count until a responder skips them
+    pub fn queue_len(&self) -> usize {
+        let buf = self.buf.lock().unwrap();
+        buf.load().0
+    }
+
+    pub fn in_flight(&self) -> usize {
+        let buf = self.buf.lock().unwrap();
+        buf.load().1
+    }
+
+    fn send_request(&mut self, data: T1, slot: BDSlot) -> Result<BDReceiverAsync<T2>, BDError> {
+        self.send_request_slot(data, slot, BDSchedule::default())
+    }
+
+    fn send_request_slot(
+        &mut self,
+        data: T1,
+        slot: BDSlot,
+        schedule: BDSchedule,
+    ) -> Result<BDReceiverAsync<T2>, BDError> {
+        let mut buf = self.buf.lock().unwrap();
+        let receiver = buf.request(data, slot, schedule)?;
+        self.sema.add_permits(1);
+        Ok(receiver)
+    }
+
+    fn no_response_error(&self) -> BDError {
+        self.buf.lock().unwrap().no_response_error()
     }
 }
 
-unsafe impl<T1, T2> Send for BDUnbRequesterAsync<T1, T2> {}
+/// `poll_ready` fails with `BDError::Disconnected` once every responder is gone
+#[cfg(feature = "tower")]
+impl<T1: Send + 'static, T2: Send + 'static> Service<T1> for BDUnbRequesterAsync<T1, T2> {
+    type Response = T2;
+    type Error = BDError;
+    type Future = Pin<Box<dyn Future<Output = Result<T2, BDError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BDError>> {
+        if let BDError::Disconnected = self.no_response_error() {
+            return Poll::Ready(Err(BDError::Disconnected));
+        }
+        if self.ready_slot.is_none() {
+            let load = self.load.clone();
+            let acquiring = self
+                .acquiring
+                .get_or_insert_with(|| Box::pin(async move { load.acquire_async().await }));
+            self.ready_slot = Some(ready!(acquiring.as_mut().poll(cx)));
+            self.acquiring = None;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, data: T1) -> Self::Future {
+        let slot = self
+            .ready_slot
+            .take()
+            .expect("poll_ready must be called before call");
+        let receiver = self.send_request(data, slot);
+        // share the state without a new requester handle, which would keep the responders
+        // connected until the call resolves
+        let buf = self.buf.clone();
+        Box::pin(async move {
+            receiver?
+                .await
+                .unwrap_or_else(|_| Err(buf.lock().unwrap().no_response_error()))
+        })
+    }
+}
+
+impl<T1, T2> Drop for BDUnbRequesterAsync<T1, T2> {
+    /// The last requester wakes the waiting responders
+    fn drop(&mut self) {
+        let mut buf = self.buf.lock().unwrap();
+        buf.requester_count -= 1;
+        if buf.requester_count == 0 {
+            for broadcast in buf.responders.values() {
+                broadcast.notify.notify_one();
+            }
+        }
+    }
+}
 
 pub struct BDUnbResponderAsync<T1, T2> {
-    buf: NonNull<Mutex<BDUnbBufferAsync<T1, T2>>>,
+    buf: Arc<Mutex<BDUnbBufferAsync<T1, T2>>>,
     sema: Semaphore,
+    id: usize,
+    notify: Arc<Notify>,
 }
 
 impl<T1, T2> BDUnbResponderAsync<T1, T2> {
-    pub async fn take_request(&mut self) -> (T1, UnboundedSenderAsync<T2>) {
-        self.sema.acquire().await;
-        let mut buf = unsafe { self.buf.clone().as_mut().lock().unwrap() };
-        buf.take_request().await
+    pub fn id(&self) -> ResponderId {
+        ResponderId(self.id)
+    }
+
+    /// `BDError::Disconnected` once every requester is gone and the queue is drained
+    pub async fn take_request(&mut self) -> Result<(T1, ReplyHandleAsync<T2>), BDError> {
+        loop {
+            if let Some(request) = self.take_broadcast() {
+                return Ok(request);
+            }
+            if self.buf.lock().unwrap().is_disconnected() {
+                return Err(BDError::Disconnected);
+            }
+            tokio::select! {
+                _ = self.sema.acquire() => {
+                    let mut buf = self.buf.lock().unwrap();
+                    if let Some(request) = buf.take_request() {
+                        return Ok(request);
+                    }
+                }
+                _ = self.notify.notified() => (),
+            }
+        }
     }
 
-    pub async fn try_take_request(&mut self) -> Option<(T1, UnboundedSenderAsync<T2>)> {
-        match self.sema.try_acquire() {
-            true => {
-                let mut buf = unsafe { self.buf.clone().as_mut().lock().unwrap() };
-                Some(buf.take_request().await)
+    pub async fn try_take_request(&mut self) -> Option<(T1, ReplyHandleAsync<T2>)> {
+        if let Some(request) = self.take_broadcast() {
+            return Some(request);
+        }
+        while self.sema.try_acquire() {
+            let mut buf = self.buf.lock().unwrap();
+            if let Some(request) = buf.take_request() {
+                return Some(request);
             }
-            false => None,
         }
+        None
+    }
+
+    /// Cancelled requests count until a responder skips them
+    pub fn queue_len(&self) -> usize {
+        let buf = self.buf.lock().unwrap();
+        buf.load().0
+    }
+
+    pub fn in_flight(&self) -> usize {
+        let buf = self.buf.lock().unwrap();
+        buf.load().1
+    }
+
+    fn take_broadcast(&mut self) -> Option<(T1, ReplyHandleAsync<T2>)> {
+        let mut buf = self.buf.lock().unwrap();
+        buf.take_broadcast(self.id)
     }
 }
 
 impl<T1, T2> Clone for BDUnbResponderAsync<T1, T2> {
     fn clone(&self) -> Self {
+        let mut buf = self.buf.lock().unwrap();
+        let (id, notify) = buf.register_responder();
         Self {
             buf: self.buf.clone(),
             sema: self.sema.clone(),
+            id,
+            notify,
         }
     }
 }
 
-unsafe impl<T1, T2> Send for BDUnbResponderAsync<T1, T2> {}
+impl<T1, T2> Drop for BDUnbResponderAsync<T1, T2> {
+    fn drop(&mut self) {
+        let mut buf = self.buf.lock().unwrap();
+        buf.responders.remove(&self.id);
+        if buf.responders.is_empty() {
+            buf.req_buf.clear();
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use super::time_series::{GetDataTimeExt, TSReceiver};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TSFramePacerError {
+    /// The frame rate is not a positive finite number, or its frame interval is out of range
+    InvalidFps(f64),
+}
+
+impl std::fmt::Display for TSFramePacerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TSFramePacerError::InvalidFps(fps) => write!(f, "invalid frame rate {fps}"),
+        }
+    }
+}
+
+impl std::error::Error for TSFramePacerError {}
+
+/// Yields the most recent due frame of a time series receiver at a fixed rate
+pub struct TSFramePacer<T> {
+    receiver: TSReceiver<T>,
+    interval: Duration,
+    next_tick: Instant,
+    last_frame: Option<T>,
+}
+
+impl<T: Clone + Sized + GetDataTimeExt> TSFramePacer<T> {
+    pub fn new(receiver: TSReceiver<T>, fps: f64) -> Result<Self, TSFramePacerError> {
+        if !(fps.is_finite() && fps > 0.0) {
+            return Err(TSFramePacerError::InvalidFps(fps));
+        }
+        let interval = Duration::try_from_secs_f64(1.0 / fps)
+            .map_err(|_| TSFramePacerError::InvalidFps(fps))?;
+        Ok(Self {
+            receiver,
+            interval,
+            next_tick: Instant::now() + interval,
+            last_frame: None,
+        })
+    }
+
+    pub fn next_frame(&mut self) -> Option<T> {
+        let now = Instant::now();
+        if self.next_tick > now {
+            sleep(self.next_tick - now);
+        }
+        self.tick()
+    }
+
+    pub async fn next_frame_async(&mut self) -> Option<T> {
+        tokio::time::sleep_until(self.next_tick.into()).await;
+        self.tick()
+    }
+
+    pub fn try_next_frame(&mut self) -> Option<T> {
+        match Instant::now() >= self.next_tick {
+            true => self.tick(),
+            false => None,
+        }
+    }
+
+    fn tick(&mut self) -> Option<T> {
+        self.next_tick += self.interval;
+        let now = Instant::now();
+        if self.next_tick <= now {
+            self.next_tick = now + self.interval;
+        }
+        if let Some(frame) = self.receiver.recv_latest_due() {
+            self.last_frame = Some(frame);
+        }
+        self.last_frame.clone()
+    }
+}
+
+impl<T> TSFramePacer<T> {
+    pub fn get_receiver(&self) -> &TSReceiver<T> {
+        &self.receiver
+    }
+}
@@ -1,6 +1,12 @@
 pub mod asynchronous;
+pub mod bd_serve;
 pub mod bidirectional;
+pub mod frame_pacer;
+pub mod playback_clock;
 pub mod time_series;
+pub mod ts_merge;
+pub mod ts_resample;
+pub mod ts_window;
 
 use crate::utils::vec_utils::VecExt;
 use std::collections::HashMap;
@@ -162,7 +168,7 @@ impl<T: Clone + Sized> UnboundedDispatchBuffer<T> {
                 return ret;
             };
             for i in 0..read_count {
-                ret.push(self.buf[i].clone());
+                ret.push(self.buf[*cur_pos + i].clone());
             }
             *cur_pos += read_count;
         }
@@ -273,7 +279,7 @@ impl<T: Clone + Sized> BoundedDispatchBuffer<T> {
                 return ret;
             };
             for i in 0..read_count {
-                ret.push(self.buf[i].clone());
+                ret.push(self.buf[*cur_pos + i].clone());
             }
             *cur_pos += read_count;
         }
@@ -343,6 +349,178 @@ impl<T> BoundedDispatchBuffer<T> {
     }
 }
 
+/// The receiver positions over the items shared by the dispatch buffers
+pub(crate) trait DispatchBuffer<T> {
+    fn parts(&self) -> (&Vec<T>, &HashMap<usize, usize>);
+
+    fn parts_mut(&mut self) -> (&mut Vec<T>, &mut HashMap<usize, usize>);
+
+    fn send_items(&mut self, data: Vec<T>)
+    where
+        T: Clone;
+
+    fn recv(&mut self, recver_index: usize) -> Option<T>
+    where
+        T: Clone;
+
+    fn recv_count(&mut self, recver_index: usize, recv_count: usize, force_count: bool) -> Vec<T>
+    where
+        T: Clone;
+
+    fn query_items(&self, start: usize, end: Option<usize>) -> Vec<T>
+    where
+        T: Clone;
+
+    fn len(&self, recver_index: usize) -> usize;
+
+    fn new_receiver(&mut self, recver_index: usize);
+
+    fn drop_receiver(&mut self, recver_index: usize);
+
+    /// Returns the number of removed items each receiver had not received yet
+    fn remove_front_while<F: Fn(&T) -> bool>(&mut self, f: F) -> Vec<(usize, usize)> {
+        let (buf, receiver_poses) = self.parts_mut();
+        let remove_count = buf.iter().take_while(|item| f(item)).count();
+        let mut unread = vec![];
+        if remove_count > 0 {
+            for (recver_index, receiver_pos) in receiver_poses.iter_mut() {
+                *receiver_pos = match *receiver_pos >= remove_count {
+                    true => *receiver_pos - remove_count,
+                    false => {
+                        unread.push((*recver_index, remove_count - *receiver_pos));
+                        0
+                    }
+                };
+            }
+            buf.drain(0..remove_count);
+        }
+        unread
+    }
+
+    fn last(&self) -> Option<&T> {
+        self.parts().0.last()
+    }
+
+    fn count_while<F: Fn(&T) -> bool>(&self, recver_index: usize, f: F) -> usize {
+        self.pending(recver_index)
+            .iter()
+            .take_while(|item| f(item))
+            .count()
+    }
+
+    /// The items not yet received by the receiver
+    fn pending(&self, recver_index: usize) -> &[T] {
+        let (buf, receiver_poses) = self.parts();
+        let cur_pos = receiver_poses.get(&recver_index).cloned().unwrap_or(0);
+        &buf[cur_pos.min(buf.len())..]
+    }
+
+    /// The items kept for the slowest receiver
+    fn total_len(&self) -> usize {
+        self.parts().0.len()
+    }
+}
+
+impl<T> DispatchBuffer<T> for UnboundedDispatchBuffer<T> {
+    fn parts(&self) -> (&Vec<T>, &HashMap<usize, usize>) {
+        (&self.buf, &self.receiver_poses)
+    }
+
+    fn parts_mut(&mut self) -> (&mut Vec<T>, &mut HashMap<usize, usize>) {
+        (&mut self.buf, &mut self.receiver_poses)
+    }
+
+    fn send_items(&mut self, data: Vec<T>)
+    where
+        T: Clone,
+    {
+        UnboundedDispatchBuffer::send_items(self, data)
+    }
+
+    fn recv(&mut self, recver_index: usize) -> Option<T>
+    where
+        T: Clone,
+    {
+        UnboundedDispatchBuffer::recv(self, recver_index)
+    }
+
+    fn recv_count(&mut self, recver_index: usize, recv_count: usize, force_count: bool) -> Vec<T>
+    where
+        T: Clone,
+    {
+        UnboundedDispatchBuffer::recv_count(self, recver_index, recv_count, force_count)
+    }
+
+    fn query_items(&self, start: usize, end: Option<usize>) -> Vec<T>
+    where
+        T: Clone,
+    {
+        UnboundedDispatchBuffer::query_items(self, start, end)
+    }
+
+    fn len(&self, recver_index: usize) -> usize {
+        UnboundedDispatchBuffer::len(self, recver_index)
+    }
+
+    fn new_receiver(&mut self, recver_index: usize) {
+        UnboundedDispatchBuffer::new_receiver(self, recver_index)
+    }
+
+    fn drop_receiver(&mut self, recver_index: usize) {
+        UnboundedDispatchBuffer::drop_receiver(self, recver_index)
+    }
+}
+
+impl<T> DispatchBuffer<T> for BoundedDispatchBuffer<T> {
+    fn parts(&self) -> (&Vec<T>, &HashMap<usize, usize>) {
+        (&self.buf, &self.receiver_poses)
+    }
+
+    fn parts_mut(&mut self) -> (&mut Vec<T>, &mut HashMap<usize, usize>) {
+        (&mut self.buf, &mut self.receiver_poses)
+    }
+
+    fn send_items(&mut self, data: Vec<T>)
+    where
+        T: Clone,
+    {
+        BoundedDispatchBuffer::send_items(self, data)
+    }
+
+    fn recv(&mut self, recver_index: usize) -> Option<T>
+    where
+        T: Clone,
+    {
+        BoundedDispatchBuffer::recv(self, recver_index)
+    }
+
+    fn recv_count(&mut self, recver_index: usize, recv_count: usize, force_count: bool) -> Vec<T>
+    where
+        T: Clone,
+    {
+        BoundedDispatchBuffer::recv_count(self, recver_index, recv_count, force_count)
+    }
+
+    fn query_items(&self, start: usize, end: Option<usize>) -> Vec<T>
+    where
+        T: Clone,
+    {
+        BoundedDispatchBuffer::query_items(self, start, end)
+    }
+
+    fn len(&self, recver_index: usize) -> usize {
+        BoundedDispatchBuffer::len(self, recver_index)
+    }
+
+    fn new_receiver(&mut self, recver_index: usize) {
+        BoundedDispatchBuffer::new_receiver(self, recver_index)
+    }
+
+    fn drop_receiver(&mut self, recver_index: usize) {
+        BoundedDispatchBuffer::drop_receiver(self, recver_index)
+    }
+}
+
 #[derive(Debug)]
 pub(crate) enum AnyBuffer<T> {
     UnboundedBuffer(UnboundedBuffer<T>),
@@ -428,6 +606,15 @@ impl<T> AnyBuffer<T> {
         }
     }
 
+    pub fn total_len(&self) -> usize {
+        match self {
+            AnyBuffer::UnboundedBuffer(buf) => buf.len(),
+            AnyBuffer::BoundedBuffer(buf) => buf.len(),
+            AnyBuffer::UnboundedDispatchBuffer(buf) => buf.total_len(),
+            AnyBuffer::BoundedDispatchBuffer(buf) => buf.total_len(),
+        }
+    }
+
     pub fn new_receiver(&mut self, recver_index: usize) {
         match self {
             AnyBuffer::UnboundedBuffer(_) => {}
@@ -0,0 +1,162 @@
+use crate::utils::time_util::NaiveDateTimeExt;
+use chrono::{Duration, NaiveDateTime};
+use std::sync::{Arc, Condvar, Mutex};
+use tokio::sync::futures::Notified;
+use tokio::sync::Notify;
+
+#[derive(Debug)]
+struct PlaybackClockState {
+    origin_data_time: NaiveDateTime,
+    /// The data time of the last seek, or the origin if the clock was never seeked
+    seek_data_time: NaiveDateTime,
+    start_data_time: NaiveDateTime,
+    start_cur_time: NaiveDateTime,
+    speed: f64,
+    paused: bool,
+}
+
+impl PlaybackClockState {
+    fn position(&self) -> NaiveDateTime {
+        if self.paused {
+            return self.start_data_time;
+        }
+        let cur_nanos = (NaiveDateTime::now() - self.start_cur_time)
+            .num_nanoseconds()
+            .unwrap_or(0);
+        self.start_data_time + Duration::nanoseconds((cur_nanos as f64 * self.speed).round() as i64)
+    }
+
+    fn rebase(&mut self) {
+        self.start_data_time = self.position();
+        self.start_cur_time = NaiveDateTime::now();
+    }
+}
+
+/// Wakes the receivers waiting on the time series channels of the clock
+#[derive(Debug, Default)]
+struct PlaybackClockWaker {
+    generation: Mutex<u64>,
+    condvar: Condvar,
+    notify: Notify,
+}
+
+/// Maps the real time to the data time of its time series channels, backwards at a negative speed
+#[derive(Debug, Clone)]
+pub struct PlaybackClock {
+    state: Arc<Mutex<PlaybackClockState>>,
+    waker: Arc<PlaybackClockWaker>,
+}
+
+impl PlaybackClock {
+    pub fn new(start_data_time: NaiveDateTime, speed: f64) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(PlaybackClockState {
+                origin_data_time: start_data_time,
+                seek_data_time: start_data_time,
+                start_data_time,
+                start_cur_time: NaiveDateTime::now(),
+                speed,
+                paused: false,
+            })),
+            waker: Arc::new(PlaybackClockWaker::default()),
+        }
+    }
+
+    pub fn position(&self) -> NaiveDateTime {
+        self.state.lock().unwrap().position()
+    }
+
+    pub fn pause(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.paused {
+            state.rebase();
+            state.paused = true;
+        }
+        drop(state);
+        self.wake();
+    }
+
+    pub fn resume(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.paused {
+            state.start_cur_time = NaiveDateTime::now();
+            state.paused = false;
+        }
+        drop(state);
+        self.wake();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().paused
+    }
+
+    pub fn seek(&self, data_time: NaiveDateTime) {
+        let mut state = self.state.lock().unwrap();
+        state.seek_data_time = data_time;
+        state.start_data_time = data_time;
+        state.start_cur_time = NaiveDateTime::now();
+        drop(state);
+        self.wake();
+    }
+
+    /// Seek back to the start data time the clock was created with
+    pub fn rewind(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.seek_data_time = state.origin_data_time;
+        state.start_data_time = state.origin_data_time;
+        state.start_cur_time = NaiveDateTime::now();
+        drop(state);
+        self.wake();
+    }
+
+    pub fn set_speed(&self, speed: f64) {
+        let mut state = self.state.lock().unwrap();
+        state.rebase();
+        state.speed = speed;
+        drop(state);
+        self.wake();
+    }
+
+    pub fn get_start_data_time(&self) -> NaiveDateTime {
+        self.state.lock().unwrap().origin_data_time
+    }
+
+    pub(crate) fn get_seek_data_time(&self) -> NaiveDateTime {
+        self.state.lock().unwrap().seek_data_time
+    }
+
+    pub fn get_speed(&self) -> f64 {
+        self.state.lock().unwrap().speed
+    }
+
+    pub fn is_reverse(&self) -> bool {
+        self.state.lock().unwrap().speed < 0.0
+    }
+
+    pub(crate) fn wake(&self) {
+        *self.waker.generation.lock().unwrap() += 1;
+        self.waker.condvar.notify_all();
+        self.waker.notify.notify_waiters();
+    }
+
+    /// Taken before checking the channel, so that no wake is missed
+    pub(crate) fn wake_generation(&self) -> u64 {
+        *self.waker.generation.lock().unwrap()
+    }
+
+    /// Block until the clock is woken after the generation, or until the timeout
+    pub(crate) fn wait_wake(&self, generation: u64, timeout: Option<std::time::Duration>) {
+        let guard = self.waker.generation.lock().unwrap();
+        let is_same = |cur: &mut u64| *cur == generation;
+        let condvar = &self.waker.condvar;
+        match timeout {
+            Some(timeout) => drop(condvar.wait_timeout_while(guard, timeout, is_same).unwrap()),
+            None => drop(condvar.wait_while(guard, is_same).unwrap()),
+        }
+    }
+
+    /// Enable the future before checking the channel, so that no wake is missed
+    pub(crate) fn notified(&self) -> Notified<'_> {
+        self.waker.notify.notified()
+    }
+}
@@ -1,8 +1,9 @@
-use super::{BoundedDispatchBuffer, UnboundedDispatchBuffer};
-use crate::utils::{time_util::NaiveDateTimeExt, vec_utils::VecExt};
-use chrono::NaiveDateTime;
-use std::ptr::{self, NonNull};
-use std::sync::Mutex;
+use super::playback_clock::PlaybackClock;
+use super::{BoundedDispatchBuffer, DispatchBuffer, UnboundedDispatchBuffer};
+use crate::utils::vec_utils::VecExt;
+use chrono::{Duration, NaiveDateTime};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
 #[cfg(feature = "metrics")]
 use {
@@ -14,64 +15,297 @@ pub trait GetDataTimeExt {
     fn get_data_time(&self) -> NaiveDateTime;
 }
 
+/// Time based retention of a time series buffer, applied on every send and recv
+#[derive(Debug, Clone, Copy)]
+pub enum TSRetention {
+    /// Keep only items whose data time is within the span of the newest item
+    MaxSpan(Duration),
+    /// Keep only items whose data time is within the age of the playback clock
+    MaxAge(Duration),
+}
+
+impl TSRetention {
+    /// The data time the items played before expire
+    fn cutoff(
+        &self,
+        newest_data_time: Option<NaiveDateTime>,
+        cur_data_time: NaiveDateTime,
+        is_reverse: bool,
+    ) -> Option<NaiveDateTime> {
+        let (data_time, keep) = match self {
+            TSRetention::MaxSpan(span) => (newest_data_time?, *span),
+            TSRetention::MaxAge(age) => (cur_data_time, *age),
+        };
+        match is_reverse {
+            false => Some(data_time - keep),
+            true => Some(data_time + keep),
+        }
+    }
+}
+
+fn is_due<T: GetDataTimeExt>(item: &T, cur_data_time: NaiveDateTime, is_reverse: bool) -> bool {
+    is_due_at(item.get_data_time(), cur_data_time, is_reverse)
+}
+
+fn is_due_at(data_time: NaiveDateTime, cur_data_time: NaiveDateTime, is_reverse: bool) -> bool {
+    match is_reverse {
+        false => data_time <= cur_data_time,
+        true => data_time >= cur_data_time,
+    }
+}
+
+fn is_expired<T: GetDataTimeExt>(item: &T, cutoff: NaiveDateTime, is_reverse: bool) -> bool {
+    match is_reverse {
+        false => item.get_data_time() < cutoff,
+        true => item.get_data_time() > cutoff,
+    }
+}
+
+/// The data time played per real time, zero while the clock is paused
+fn clock_speed(clock: &PlaybackClock) -> f64 {
+    match clock.is_paused() {
+        true => 0.0,
+        false => clock.get_speed(),
+    }
+}
+
+fn data_span<'a, T: GetDataTimeExt + 'a>(
+    items: impl IntoIterator<Item = Option<&'a T>>,
+) -> Option<(NaiveDateTime, NaiveDateTime)> {
+    items
+        .into_iter()
+        .flatten()
+        .map(|item| item.get_data_time())
+        .fold(None, |span, data_time| match span {
+            Some((oldest, newest)) => Some((data_time.min(oldest), data_time.max(newest))),
+            None => Some((data_time, data_time)),
+        })
+}
+
+/// Per receiver offset and speed of the dispatch time series buffers
+#[derive(Debug, Default)]
+struct TSReceiverViews {
+    views: HashMap<usize, (Duration, f64)>,
+}
+
+impl TSReceiverViews {
+    fn set(&mut self, recver_index: usize, offset: Duration, speed: f64) {
+        self.views.insert(recver_index, (offset, speed));
+    }
+
+    fn remove(&mut self, recver_index: usize) {
+        self.views.remove(&recver_index);
+    }
+
+    /// The speed scales the data time played since the last seek, the seek itself is not scaled
+    fn view_position(&self, offset: Duration, speed: f64, clock: &PlaybackClock) -> NaiveDateTime {
+        let seek_data_time = clock.get_seek_data_time();
+        let nanos = (clock.position() - seek_data_time)
+            .num_nanoseconds()
+            .unwrap_or(0);
+        let position =
+            seek_data_time + Duration::nanoseconds((nanos as f64 * speed).round() as i64);
+        match clock.is_reverse() {
+            false => position - offset,
+            true => position + offset,
+        }
+    }
+
+    fn position(&self, recver_index: usize, clock: &PlaybackClock) -> NaiveDateTime {
+        match self.views.get(&recver_index) {
+            Some((offset, speed)) => self.view_position(*offset, *speed, clock),
+            None => clock.position(),
+        }
+    }
+
+    fn speed(&self, recver_index: usize, clock: &PlaybackClock) -> f64 {
+        let view_speed = self.views.get(&recver_index).map_or(1.0, |view| view.1);
+        clock_speed(clock) * view_speed
+    }
+
+    /// The playback direction of the receiver, kept while the clock is paused
+    fn is_reverse(&self, recver_index: usize, clock: &PlaybackClock) -> bool {
+        let view_speed = self.views.get(&recver_index).map_or(1.0, |view| view.1);
+        clock.get_speed() * view_speed < 0.0
+    }
+
+    /// The position of the receiver that is furthest ahead
+    fn lead_position(&self, clock: &PlaybackClock) -> NaiveDateTime {
+        let positions = self
+            .views
+            .values()
+            .map(|(offset, speed)| self.view_position(*offset, *speed, clock));
+        match clock.is_reverse() {
+            false => positions.fold(clock.position(), |lead, position| lead.max(position)),
+            true => positions.fold(clock.position(), |lead, position| lead.min(position)),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct TSUnboundedBuffer<T> {
     buf: Vec<T>,
-    start_data_time: NaiveDateTime,
-    start_cur_time: NaiveDateTime,
-    speed: f64,
+    clock: PlaybackClock,
+    retention: Option<TSRetention>,
+    finished: bool,
+    looping: bool,
+    /// The released items, replayed once the finished stream runs out while looping
+    history: Vec<T>,
+    history_bound: Option<usize>,
+    /// Shifts the data time of this channel against the clock when the loop restarts
+    loop_offset: Duration,
 }
 
 impl<T: Clone + Sized + GetDataTimeExt> TSUnboundedBuffer<T> {
     pub fn send(&mut self, data: T) {
         self.buf.push(data);
+        self.apply_retention();
     }
 
     pub fn send_items(&mut self, data: Vec<T>) {
         self.buf.extend(data);
+        self.apply_retention();
     }
 
     pub fn recv(&mut self) -> Option<T> {
-        if self.is_valid(0) {
-            return Some(self.buf.remove(0));
-        }
-        None
+        self.recv_count(1, true).pop()
     }
 
     pub fn recv_count(&mut self, recv_count: usize, force_count: bool) -> Vec<T> {
+        self.apply_loop();
+        self.recv_count_at(recv_count, force_count, self.cur_data_time())
+    }
+
+    /// Receive the items that are due at the given data time instead of the clock position
+    pub fn recv_count_at(
+        &mut self,
+        recv_count: usize,
+        force_count: bool,
+        cur_data_time: NaiveDateTime,
+    ) -> Vec<T> {
+        self.apply_retention();
         let mut read_count = if recv_count <= self.buf.len() {
             recv_count
-        } else if !force_count && self.buf.len() > 0 {
-            self.buf.len() - recv_count
+        } else if !force_count {
+            self.buf.len()
         } else {
             return vec![];
         };
         while read_count > 0 {
-            if self.is_valid(read_count - 1) {
+            if self.is_valid(read_count - 1, cur_data_time) {
                 break;
             }
             read_count -= 1;
         }
-        self.buf.drain(0..read_count).collect()
+        let items: Vec<T> = match self.clock.is_reverse() {
+            false => self.buf.drain(0..read_count).collect(),
+            true => self
+                .buf
+                .drain(self.buf.len() - read_count..)
+                .rev()
+                .collect(),
+        };
+        if self.looping {
+            self.history.extend(items.iter().cloned());
+            if let Some(bound) = self.history_bound {
+                let remove_count = self.history.len().saturating_sub(bound);
+                self.history.drain(0..remove_count);
+            }
+        }
+        items
     }
 
-    fn is_valid(&self, index: usize) -> bool {
-        if self.buf.len() <= index {
+    /// Whether the nth item in release order is due
+    fn is_valid(&self, nth: usize, cur_data_time: NaiveDateTime) -> bool {
+        if self.buf.len() <= nth {
             return false;
         }
-        let dest_nanos = (self.buf[index].get_data_time() - self.start_data_time)
-            .num_nanoseconds()
-            .unwrap_or(0);
-        let cur_nanos = (NaiveDateTime::now() - self.start_cur_time)
-            .num_nanoseconds()
-            .unwrap_or(0);
-        let cur_nanos = (cur_nanos as f64 * self.speed).round() as i64;
-        dest_nanos <= cur_nanos
+        let index = match self.clock.is_reverse() {
+            false => nth,
+            true => self.buf.len() - 1 - nth,
+        };
+        is_due(&self.buf[index], cur_data_time, self.clock.is_reverse())
+    }
+
+    /// The data time of the item released last
+    pub fn newest_data_time(&self) -> Option<NaiveDateTime> {
+        let newest = match self.clock.is_reverse() {
+            false => self.buf.last(),
+            true => self.buf.first(),
+        };
+        newest
+            .or_else(|| self.history.last())
+            .map(|item| item.get_data_time())
+    }
+
+    /// The next item in release order
+    pub fn head(&self) -> Option<&T> {
+        match self.clock.is_reverse() {
+            false => self.buf.first(),
+            true => self.buf.last(),
+        }
+    }
+
+    pub fn data_span(&self) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        data_span([self.buf.first(), self.buf.last()])
+    }
+
+    /// Remove the items played before the cutoff, also the released ones kept for the loop
+    pub fn remove_expired(&mut self, cutoff: NaiveDateTime, is_reverse: bool) {
+        match is_reverse {
+            false => {
+                let remove_count = self
+                    .buf
+                    .count_front_while(|item| is_expired(item, cutoff, is_reverse));
+                self.buf.drain(0..remove_count);
+            }
+            true => {
+                let keep_count = self.buf.len()
+                    - self
+                        .buf
+                        .iter()
+                        .rev()
+                        .take_while(|item| is_expired(*item, cutoff, is_reverse))
+                        .count();
+                self.buf.truncate(keep_count);
+            }
+        }
+        self.history
+            .retain(|item| !is_expired(item, cutoff, is_reverse));
+    }
+
+    fn apply_retention(&mut self) {
+        if let Some(retention) = self.retention {
+            let is_reverse = self.clock.is_reverse();
+            let cutoff =
+                retention.cutoff(self.newest_data_time(), self.cur_data_time(), is_reverse);
+            if let Some(cutoff) = cutoff {
+                self.remove_expired(cutoff, is_reverse);
+            }
+        }
     }
 
+    /// Refill the buffer with the released items once the finished stream runs out
+    pub fn apply_loop(&mut self) {
+        let is_drained = self.finished && self.buf.is_empty();
+        if !self.looping || !is_drained || self.history.is_empty() {
+            return;
+        }
+        self.buf = std::mem::take(&mut self.history);
+        self.buf.sort_by_key(|item| item.get_data_time());
+        if let Some(start) = self.head().map(|item| item.get_data_time()) {
+            self.loop_offset = self.clock.position() - start;
+        }
+    }
+
+    /// Keep at most `bound` items, dropping the items released first
     pub fn part_queue_apply_bound(&mut self, bound: usize) {
-        if self.buf.len() > bound {
-            self.buf = self.buf.split_at(self.buf.len() - bound).1.to_vec();
+        let remove_count = self.buf.len().saturating_sub(bound);
+        match self.clock.is_reverse() {
+            false => {
+                self.buf.drain(0..remove_count);
+            }
+            true => self.buf.truncate(bound),
         }
     }
 
@@ -81,190 +315,164 @@ impl<T: Clone + Sized + GetDataTimeExt> TSUnboundedBuffer<T> {
 }
 
 impl<T> TSUnboundedBuffer<T> {
-    pub fn new(start_data_time: NaiveDateTime, speed: f64) -> Self {
+    pub fn new(clock: PlaybackClock) -> Self {
         Self {
             buf: vec![],
-            start_data_time,
-            start_cur_time: NaiveDateTime::now(),
-            speed,
+            clock,
+            retention: None,
+            finished: false,
+            looping: false,
+            history: vec![],
+            history_bound: None,
+            loop_offset: Duration::zero(),
         }
     }
 
     pub fn len(&self) -> usize {
         self.buf.len()
     }
+
+    /// The data time this channel has played to, the clock position shifted by the loop
+    pub fn cur_data_time(&self) -> NaiveDateTime {
+        self.clock.position() - self.loop_offset
+    }
+
+    pub fn get_loop_offset(&self) -> Duration {
+        self.loop_offset
+    }
+
+    pub fn get_clock(&self) -> &PlaybackClock {
+        &self.clock
+    }
+
+    pub fn set_retention(&mut self, retention: Option<TSRetention>) {
+        self.retention = retention;
+    }
+
+    pub fn set_loop(&mut self, looping: bool) {
+        self.looping = looping;
+        if !looping {
+            self.history.clear();
+        }
+    }
+
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    /// Whether the stream is finished and will not loop
+    pub fn is_ended(&self) -> bool {
+        self.finished && (!self.looping || self.history.is_empty())
+    }
 }
 
 #[derive(Debug)]
 pub(crate) struct TSBoundedBuffer<T> {
-    buf: Vec<T>,
+    buffer: TSUnboundedBuffer<T>,
     bounded: usize,
-    start_data_time: NaiveDateTime,
-    start_cur_time: NaiveDateTime,
-    speed: f64,
 }
 
 impl<T: Clone + Sized + GetDataTimeExt> TSBoundedBuffer<T> {
     pub fn send(&mut self, data: T) {
-        self.buf.push(data);
-        if self.buf.len() > self.bounded {
-            self.buf.remove(0);
-        }
+        self.buffer.send(data);
+        self.buffer.part_queue_apply_bound(self.bounded);
     }
 
     pub fn send_items(&mut self, data: Vec<T>) {
-        self.buf.extend(data);
-        if self.buf.len() > self.bounded {
-            self.buf = self.buf.split_at(self.buf.len() - self.bounded).1.to_vec();
-        }
+        self.buffer.send_items(data);
+        self.buffer.part_queue_apply_bound(self.bounded);
     }
 
     pub fn recv(&mut self) -> Option<T> {
-        if !self.buf.is_empty() {
-            if self.is_valid(0) {
-                return Some(self.buf.remove(0));
-            }
-        }
-        None
+        self.buffer.recv()
     }
 
     pub fn recv_count(&mut self, recv_count: usize, force_count: bool) -> Vec<T> {
-        let mut read_count = if recv_count <= self.buf.len() {
-            recv_count
-        } else if !force_count && self.buf.len() > 0 {
-            self.buf.len() - recv_count
-        } else {
-            return vec![];
-        };
-        while read_count > 0 {
-            if self.is_valid(read_count) {
-                break;
-            }
-            read_count -= 1;
-        }
-        self.buf.drain(0..read_count).collect()
+        self.buffer.recv_count(recv_count, force_count)
     }
 
-    fn is_valid(&self, index: usize) -> bool {
-        let dest_nanos = (self.buf[index].get_data_time() - self.start_data_time)
-            .num_nanoseconds()
-            .unwrap_or(0);
-        let cur_nanos = (NaiveDateTime::now() - self.start_cur_time)
-            .num_nanoseconds()
-            .unwrap_or(0);
-        let cur_nanos = (cur_nanos as f64 * self.speed).round() as i64;
-        dest_nanos <= cur_nanos
+    pub fn query_items(&self, start: usize, end: Option<usize>) -> Vec<T> {
+        self.buffer.query_items(start, end)
     }
 
-    pub fn query_items(&self, start: usize, end: Option<usize>) -> Vec<T> {
-        self.buf.query_items(start, end)
+    pub fn head(&self) -> Option<&T> {
+        self.buffer.head()
+    }
+
+    pub fn data_span(&self) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        self.buffer.data_span()
     }
 }
 
 impl<T> TSBoundedBuffer<T> {
-    pub fn new(bounded: usize, start_data_time: NaiveDateTime, speed: f64) -> Self {
-        Self {
-            buf: vec![],
-            bounded,
-            start_data_time,
-            start_cur_time: NaiveDateTime::now(),
-            speed,
-        }
+    pub fn new(bounded: usize, clock: PlaybackClock) -> Self {
+        let mut buffer = TSUnboundedBuffer::<T>::new(clock);
+        buffer.history_bound = Some(bounded);
+        Self { buffer, bounded }
     }
 
     pub fn len(&self) -> usize {
-        self.buf.len()
+        self.buffer.len()
     }
-}
 
-#[derive(Debug)]
-pub(crate) struct TSUnboundedDispatchBuffer<T> {
-    pre_buffer: TSUnboundedBuffer<T>,
-    post_buffer: UnboundedDispatchBuffer<T>,
-}
+    pub fn cur_data_time(&self) -> NaiveDateTime {
+        self.buffer.cur_data_time()
+    }
 
-impl<T: Clone + Sized + GetDataTimeExt> TSUnboundedDispatchBuffer<T> {
-    pub fn send(&mut self, data: T) {
-        self.pre_buffer.send(data);
+    pub fn get_clock(&self) -> &PlaybackClock {
+        self.buffer.get_clock()
     }
 
-    pub fn send_items(&mut self, data: Vec<T>) {
-        self.pre_buffer.send_items(data);
+    pub fn set_retention(&mut self, retention: Option<TSRetention>) {
+        self.buffer.set_retention(retention);
     }
 
-    pub fn recv(&mut self, recver_index: usize) -> Option<T> {
-        let tmp_data = self.pre_buffer.recv_count(self.pre_buffer.len(), false);
-        self.post_buffer.send_items(tmp_data);
-        self.post_buffer.recv(recver_index)
+    pub fn set_loop(&mut self, looping: bool) {
+        self.buffer.set_loop(looping);
     }
 
-    pub fn recv_count(
-        &mut self,
-        recver_index: usize,
-        recv_count: usize,
-        force_count: bool,
-    ) -> Vec<T> {
-        let tmp_data = self.pre_buffer.recv_count(self.pre_buffer.len(), false);
-        self.post_buffer.send_items(tmp_data);
-        self.post_buffer
-            .recv_count(recver_index, recv_count, force_count)
+    pub fn finish(&mut self) {
+        self.buffer.finish();
     }
 
-    pub fn query_items(&self, start: usize, end: Option<usize>) -> Vec<T> {
-        let mut start = start;
-        let mut end = end.unwrap_or(self.len(usize::MAX));
-        let mut post_len = self.post_buffer.len(usize::MAX);
-        let mut items = vec![];
-        {
-            let post_skip = post_len.min(start);
-            if post_skip > 0 {
-                start -= post_skip;
-                end -= post_skip;
-                post_len -= post_skip;
-            }
-        }
-        if post_len > 0 {
-            assert!(start == 0);
-            let post_take = post_len.min(end);
-            items.extend(self.post_buffer.query_items(0, Some(post_take)));
-            end -= post_take;
-            if end == 0 {
-                return items;
-            }
-        }
-        end = end.min(self.pre_buffer.len());
-        if end > start {
-            items.extend(self.pre_buffer.query_items(start, Some(end)));
-        }
-        items
+    pub fn is_ended(&self) -> bool {
+        self.buffer.is_ended()
     }
 }
 
-impl<T> TSUnboundedDispatchBuffer<T> {
-    pub fn new(start_data_time: NaiveDateTime, speed: f64) -> Self {
-        Self {
-            pre_buffer: TSUnboundedBuffer::<T>::new(start_data_time, speed),
-            post_buffer: UnboundedDispatchBuffer::<T>::new(),
-        }
-    }
+#[derive(Debug)]
+pub(crate) struct TSDispatchBuffer<T, B> {
+    pre_buffer: TSUnboundedBuffer<T>,
+    post_buffer: B,
+    retention: Option<TSRetention>,
+    views: TSReceiverViews,
+    /// The released items each receiver lost to the retention before receiving them
+    dropped: HashMap<usize, usize>,
+}
 
-    pub fn len(&self, recver_index: usize) -> usize {
-        self.pre_buffer.len() + self.post_buffer.len(recver_index)
-    }
+pub(crate) type TSUnboundedDispatchBuffer<T> = TSDispatchBuffer<T, UnboundedDispatchBuffer<T>>;
 
-    pub fn new_receiver(&mut self, recver_index: usize) {
-        self.post_buffer.new_receiver(recver_index);
+pub(crate) type TSBoundedDispatchBuffer<T> = TSDispatchBuffer<T, BoundedDispatchBuffer<T>>;
+
+impl<T: Clone + Sized + GetDataTimeExt> TSUnboundedDispatchBuffer<T> {
+    pub fn send(&mut self, data: T) {
+        self.pre_buffer.send(data);
+        self.apply_retention();
     }
 
-    pub fn drop_receiver(&mut self, recver_index: usize) {
-        self.post_buffer.drop_receiver(recver_index);
+    pub fn send_items(&mut self, data: Vec<T>) {
+        self.pre_buffer.send_items(data);
+        self.apply_retention();
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct TSBoundedDispatchBuffer<T> {
-    pre_buffer: TSUnboundedBuffer<T>,
-    post_buffer: BoundedDispatchBuffer<T>,
+impl<T> TSUnboundedDispatchBuffer<T> {
+    pub fn new(clock: PlaybackClock) -> Self {
+        Self::with_buffers(
+            TSUnboundedBuffer::<T>::new(clock),
+            UnboundedDispatchBuffer::<T>::new(),
+        )
+    }
 }
 
 impl<T: Clone + Sized + GetDataTimeExt> TSBoundedDispatchBuffer<T> {
@@ -274,6 +482,7 @@ impl<T: Clone + Sized + GetDataTimeExt> TSBoundedDispatchBuffer<T> {
             .post_buffer
             .part_queue_get_residue_count(self.pre_buffer.len());
         self.pre_buffer.part_queue_apply_bound(bound);
+        self.apply_retention();
     }
 
     pub fn send_items(&mut self, data: Vec<T>) {
@@ -282,12 +491,24 @@ impl<T: Clone + Sized + GetDataTimeExt> TSBoundedDispatchBuffer<T> {
             .post_buffer
             .part_queue_get_residue_count(self.pre_buffer.len());
         self.pre_buffer.part_queue_apply_bound(bound);
+        self.apply_retention();
     }
+}
 
+impl<T> TSBoundedDispatchBuffer<T> {
+    pub fn new(bounded: usize, clock: PlaybackClock) -> Self {
+        let mut pre_buffer = TSUnboundedBuffer::<T>::new(clock);
+        pre_buffer.history_bound = Some(bounded);
+        Self::with_buffers(pre_buffer, BoundedDispatchBuffer::<T>::new(bounded))
+    }
+}
+
+impl<T: Clone + Sized + GetDataTimeExt, B: DispatchBuffer<T>> TSDispatchBuffer<T, B> {
     pub fn recv(&mut self, recver_index: usize) -> Option<T> {
-        let tmp_data = self.pre_buffer.recv_count(self.pre_buffer.len(), false);
-        self.post_buffer.send_items(tmp_data);
-        self.post_buffer.recv(recver_index)
+        match self.due_count(recver_index) > 0 {
+            true => self.post_buffer.recv(recver_index),
+            false => None,
+        }
     }
 
     pub fn recv_count(
@@ -296,10 +517,34 @@ impl<T: Clone + Sized + GetDataTimeExt> TSBoundedDispatchBuffer<T> {
         recv_count: usize,
         force_count: bool,
     ) -> Vec<T> {
-        let tmp_data = self.pre_buffer.recv_count(self.pre_buffer.len(), false);
+        let due_count = self.due_count(recver_index);
+        let read_count = if recv_count <= due_count {
+            recv_count
+        } else if !force_count {
+            due_count
+        } else {
+            return vec![];
+        };
+        self.post_buffer.recv_count(recver_index, read_count, true)
+    }
+
+    /// Move the items due for the leading receiver to the post buffer
+    fn due_count(&mut self, recver_index: usize) -> usize {
+        if self.post_buffer.total_len() == 0 {
+            self.pre_buffer.apply_loop();
+        }
+        self.apply_retention();
+        let loop_offset = self.pre_buffer.get_loop_offset();
+        let clock = self.pre_buffer.get_clock();
+        let lead_position = self.views.lead_position(clock) - loop_offset;
+        let tmp_data = self
+            .pre_buffer
+            .recv_count_at(usize::MAX, false, lead_position);
         self.post_buffer.send_items(tmp_data);
+        let is_reverse = self.pre_buffer.get_clock().is_reverse();
+        let position = self.playback_position(recver_index);
         self.post_buffer
-            .recv_count(recver_index, recv_count, force_count)
+            .count_while(recver_index, |item| is_due(item, position, is_reverse))
     }
 
     pub fn query_items(&self, start: usize, end: Option<usize>) -> Vec<T> {
@@ -330,13 +575,52 @@ impl<T: Clone + Sized + GetDataTimeExt> TSBoundedDispatchBuffer<T> {
         }
         items
     }
+
+    pub fn head(&self, recver_index: usize) -> Option<&T> {
+        let pending = self.post_buffer.pending(recver_index);
+        pending.first().or_else(|| self.pre_buffer.head())
+    }
+
+    pub fn data_span(&self, recver_index: usize) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        let pending = self.post_buffer.pending(recver_index);
+        let pre_span = self.pre_buffer.data_span();
+        let post_span = data_span([pending.first(), pending.last()]);
+        match (pre_span, post_span) {
+            (Some(pre), Some(post)) => Some((pre.0.min(post.0), pre.1.max(post.1))),
+            (span, None) | (None, span) => span,
+        }
+    }
+
+    fn apply_retention(&mut self) {
+        if let Some(retention) = self.retention {
+            let is_reverse = self.pre_buffer.get_clock().is_reverse();
+            let newest = match self.pre_buffer.len() > 0 {
+                true => self.pre_buffer.newest_data_time(),
+                false => self.post_buffer.last().map(|item| item.get_data_time()),
+            };
+            let cur_data_time = self.pre_buffer.cur_data_time();
+            if let Some(cutoff) = retention.cutoff(newest, cur_data_time, is_reverse) {
+                // the post buffer is in release order, its expired items are at the front
+                let unread = self
+                    .post_buffer
+                    .remove_front_while(|item| is_expired(item, cutoff, is_reverse));
+                for (recver_index, count) in unread {
+                    *self.dropped.entry(recver_index).or_insert(0) += count;
+                }
+                self.pre_buffer.remove_expired(cutoff, is_reverse);
+            }
+        }
+    }
 }
 
-impl<T> TSBoundedDispatchBuffer<T> {
-    pub fn new(bounded: usize, start_data_time: NaiveDateTime, speed: f64) -> Self {
+impl<T, B: DispatchBuffer<T>> TSDispatchBuffer<T, B> {
+    fn with_buffers(pre_buffer: TSUnboundedBuffer<T>, post_buffer: B) -> Self {
         Self {
-            pre_buffer: TSUnboundedBuffer::<T>::new(start_data_time, speed),
-            post_buffer: BoundedDispatchBuffer::<T>::new(bounded),
+            pre_buffer,
+            post_buffer,
+            retention: None,
+            views: TSReceiverViews::default(),
+            dropped: HashMap::new(),
         }
     }
 
@@ -350,6 +634,52 @@ impl<T> TSBoundedDispatchBuffer<T> {
 
     pub fn drop_receiver(&mut self, recver_index: usize) {
         self.post_buffer.drop_receiver(recver_index);
+        self.views.remove(recver_index);
+        self.dropped.remove(&recver_index);
+    }
+
+    /// Take the number of items the receiver lost to the retention since the last call
+    #[cfg(feature = "metrics")]
+    pub fn take_dropped(&mut self, recver_index: usize) -> usize {
+        self.dropped.remove(&recver_index).unwrap_or(0)
+    }
+
+    pub fn set_receiver_view(&mut self, recver_index: usize, offset: Duration, speed: f64) {
+        self.views.set(recver_index, offset, speed);
+    }
+
+    pub fn playback_speed(&self, recver_index: usize) -> f64 {
+        self.views.speed(recver_index, self.pre_buffer.get_clock())
+    }
+
+    pub fn is_reverse(&self, recver_index: usize) -> bool {
+        self.views
+            .is_reverse(recver_index, self.pre_buffer.get_clock())
+    }
+
+    pub fn playback_position(&self, recver_index: usize) -> NaiveDateTime {
+        let clock = self.pre_buffer.get_clock();
+        self.views.position(recver_index, clock) - self.pre_buffer.get_loop_offset()
+    }
+
+    pub fn set_retention(&mut self, retention: Option<TSRetention>) {
+        self.retention = retention;
+    }
+
+    pub fn get_clock(&self) -> &PlaybackClock {
+        self.pre_buffer.get_clock()
+    }
+
+    pub fn set_loop(&mut self, looping: bool) {
+        self.pre_buffer.set_loop(looping);
+    }
+
+    pub fn finish(&mut self) {
+        self.pre_buffer.finish();
+    }
+
+    pub fn is_ended(&self) -> bool {
+        self.pre_buffer.is_ended()
     }
 }
 
@@ -407,6 +737,25 @@ impl<T: Clone + Sized + GetDataTimeExt> TSAnyBuffer<T> {
         }
     }
 
+    pub fn head_data_time(&self, recver_index: usize) -> Option<NaiveDateTime> {
+        let head = match self {
+            TSAnyBuffer::UnboundedBuffer(buf) => buf.head(),
+            TSAnyBuffer::BoundedBuffer(buf) => buf.head(),
+            TSAnyBuffer::UnboundedDispatchBuffer(buf) => buf.head(recver_index),
+            TSAnyBuffer::BoundedDispatchBuffer(buf) => buf.head(recver_index),
+        };
+        head.map(|item| item.get_data_time())
+    }
+
+    pub fn data_span(&self, recver_index: usize) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        match self {
+            TSAnyBuffer::UnboundedBuffer(buf) => buf.data_span(),
+            TSAnyBuffer::BoundedBuffer(buf) => buf.data_span(),
+            TSAnyBuffer::UnboundedDispatchBuffer(buf) => buf.data_span(recver_index),
+            TSAnyBuffer::BoundedDispatchBuffer(buf) => buf.data_span(recver_index),
+        }
+    }
+
     pub fn query_items(&self, start: usize, end: Option<usize>) -> Vec<T> {
         match self {
             TSAnyBuffer::UnboundedBuffer(buf) => buf.query_items(start, end),
@@ -418,26 +767,17 @@ impl<T: Clone + Sized + GetDataTimeExt> TSAnyBuffer<T> {
 }
 
 impl<T> TSAnyBuffer<T> {
-    pub fn new(
-        bounded: Option<usize>,
-        dispatch: bool,
-        start_data_time: NaiveDateTime,
-        speed: f64,
-    ) -> Self {
+    pub fn new(bounded: Option<usize>, dispatch: bool, clock: PlaybackClock) -> Self {
         match (bounded, dispatch) {
-            (None, false) => {
-                TSAnyBuffer::UnboundedBuffer(TSUnboundedBuffer::<T>::new(start_data_time, speed))
+            (None, false) => TSAnyBuffer::UnboundedBuffer(TSUnboundedBuffer::<T>::new(clock)),
+            (Some(bounded), false) => {
+                TSAnyBuffer::BoundedBuffer(TSBoundedBuffer::<T>::new(bounded, clock))
+            }
+            (None, true) => {
+                TSAnyBuffer::UnboundedDispatchBuffer(TSUnboundedDispatchBuffer::<T>::new(clock))
             }
-            (Some(bounded), false) => TSAnyBuffer::BoundedBuffer(TSBoundedBuffer::<T>::new(
-                bounded,
-                start_data_time,
-                speed,
-            )),
-            (None, true) => TSAnyBuffer::UnboundedDispatchBuffer(
-                TSUnboundedDispatchBuffer::<T>::new(start_data_time, speed),
-            ),
             (Some(bounded), true) => TSAnyBuffer::BoundedDispatchBuffer(
-                TSBoundedDispatchBuffer::<T>::new(bounded, start_data_time, speed),
+                TSBoundedDispatchBuffer::<T>::new(bounded, clock),
             ),
         }
     }
@@ -468,6 +808,131 @@ impl<T> TSAnyBuffer<T> {
             TSAnyBuffer::BoundedDispatchBuffer(buf) => buf.drop_receiver(recver_index),
         }
     }
+
+    pub fn set_retention(&mut self, retention: Option<TSRetention>) {
+        match self {
+            TSAnyBuffer::UnboundedBuffer(buf) => buf.set_retention(retention),
+            TSAnyBuffer::BoundedBuffer(buf) => buf.set_retention(retention),
+            TSAnyBuffer::UnboundedDispatchBuffer(buf) => buf.set_retention(retention),
+            TSAnyBuffer::BoundedDispatchBuffer(buf) => buf.set_retention(retention),
+        }
+    }
+
+    pub fn set_loop(&mut self, looping: bool) {
+        match self {
+            TSAnyBuffer::UnboundedBuffer(buf) => buf.set_loop(looping),
+            TSAnyBuffer::BoundedBuffer(buf) => buf.set_loop(looping),
+            TSAnyBuffer::UnboundedDispatchBuffer(buf) => buf.set_loop(looping),
+            TSAnyBuffer::BoundedDispatchBuffer(buf) => buf.set_loop(looping),
+        }
+    }
+
+    pub fn finish(&mut self) {
+        match self {
+            TSAnyBuffer::UnboundedBuffer(buf) => buf.finish(),
+            TSAnyBuffer::BoundedBuffer(buf) => buf.finish(),
+            TSAnyBuffer::UnboundedDispatchBuffer(buf) => buf.finish(),
+            TSAnyBuffer::BoundedDispatchBuffer(buf) => buf.finish(),
+        }
+    }
+
+    pub fn is_ended(&self) -> bool {
+        match self {
+            TSAnyBuffer::UnboundedBuffer(buf) => buf.is_ended(),
+            TSAnyBuffer::BoundedBuffer(buf) => buf.is_ended(),
+            TSAnyBuffer::UnboundedDispatchBuffer(buf) => buf.is_ended(),
+            TSAnyBuffer::BoundedDispatchBuffer(buf) => buf.is_ended(),
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn take_dropped(&mut self, recver_index: usize) -> usize {
+        match self {
+            TSAnyBuffer::UnboundedBuffer(_) => 0,
+            TSAnyBuffer::BoundedBuffer(_) => 0,
+            TSAnyBuffer::UnboundedDispatchBuffer(buf) => buf.take_dropped(recver_index),
+            TSAnyBuffer::BoundedDispatchBuffer(buf) => buf.take_dropped(recver_index),
+        }
+    }
+
+    /// False if the buffer is not in dispatch mode, which has no receiver views
+    pub fn set_receiver_view(&mut self, recver_index: usize, offset: Duration, speed: f64) -> bool {
+        match self {
+            TSAnyBuffer::UnboundedBuffer(_) => return false,
+            TSAnyBuffer::BoundedBuffer(_) => return false,
+            TSAnyBuffer::UnboundedDispatchBuffer(buf) => {
+                buf.set_receiver_view(recver_index, offset, speed)
+            }
+            TSAnyBuffer::BoundedDispatchBuffer(buf) => {
+                buf.set_receiver_view(recver_index, offset, speed)
+            }
+        }
+        true
+    }
+
+    pub fn playback_position(&self, recver_index: usize) -> NaiveDateTime {
+        match self {
+            TSAnyBuffer::UnboundedBuffer(buf) => buf.cur_data_time(),
+            TSAnyBuffer::BoundedBuffer(buf) => buf.cur_data_time(),
+            TSAnyBuffer::UnboundedDispatchBuffer(buf) => buf.playback_position(recver_index),
+            TSAnyBuffer::BoundedDispatchBuffer(buf) => buf.playback_position(recver_index),
+        }
+    }
+
+    pub fn playback_speed(&self, recver_index: usize) -> f64 {
+        match self {
+            TSAnyBuffer::UnboundedBuffer(buf) => clock_speed(buf.get_clock()),
+            TSAnyBuffer::BoundedBuffer(buf) => clock_speed(buf.get_clock()),
+            TSAnyBuffer::UnboundedDispatchBuffer(buf) => buf.playback_speed(recver_index),
+            TSAnyBuffer::BoundedDispatchBuffer(buf) => buf.playback_speed(recver_index),
+        }
+    }
+
+    pub fn is_reverse(&self, recver_index: usize) -> bool {
+        match self {
+            TSAnyBuffer::UnboundedBuffer(buf) => buf.get_clock().is_reverse(),
+            TSAnyBuffer::BoundedBuffer(buf) => buf.get_clock().is_reverse(),
+            TSAnyBuffer::UnboundedDispatchBuffer(buf) => buf.is_reverse(recver_index),
+            TSAnyBuffer::BoundedDispatchBuffer(buf) => buf.is_reverse(recver_index),
+        }
+    }
+
+    pub fn get_clock(&self) -> &PlaybackClock {
+        match self {
+            TSAnyBuffer::UnboundedBuffer(buf) => buf.get_clock(),
+            TSAnyBuffer::BoundedBuffer(buf) => buf.get_clock(),
+            TSAnyBuffer::UnboundedDispatchBuffer(buf) => buf.get_clock(),
+            TSAnyBuffer::BoundedDispatchBuffer(buf) => buf.get_clock(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TSEvent<T> {
+    Item(T),
+    /// No item arrived between the two data times for longer than the expected maximum gap
+    Gap {
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    },
+    /// The sender finished the stream and all of its items have been received
+    Finished,
+}
+
+pub(crate) struct TSGapDetector<T> {
+    max_gap: Duration,
+    last_data_time: NaiveDateTime,
+    heartbeat: Option<Box<dyn Fn(NaiveDateTime) -> T + Send>>,
+}
+
+impl<T> std::fmt::Debug for TSGapDetector<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TSGapDetector")
+            .field("max_gap", &self.max_gap)
+            .field("last_data_time", &self.last_data_time)
+            .field("heartbeat", &self.heartbeat.is_some())
+            .finish()
+    }
 }
 
 #[derive(Debug)]
@@ -476,6 +941,7 @@ pub(crate) struct TSChannel<T> {
     receiver_count: usize,
     max_receiver_index: usize,
     buf: TSAnyBuffer<T>,
+    gap_detectors: HashMap<usize, TSGapDetector<T>>,
     #[cfg(feature = "metrics")]
     metrics_mgr: MetricsManager,
 }
@@ -484,8 +950,7 @@ impl<T> TSChannel<T> {
     pub fn new(
         bounded: Option<usize>,
         dispatch: bool,
-        start_data_time: NaiveDateTime,
-        speed: f64,
+        clock: PlaybackClock,
         #[cfg(feature = "metrics")] caller: &'static Location<'static>,
     ) -> (TSSender<T>, TSReceiver<T>) {
         #[cfg(feature = "metrics")]
@@ -495,17 +960,18 @@ impl<T> TSChannel<T> {
             let receiver_idx = metrics_mgr.new_metrics_index(caller, HolderType::Receiver);
             (metrics_mgr, sender_idx, receiver_idx)
         };
-        let chan = NonNull::from(Box::leak(Box::new(Mutex::new(TSChannel {
+        let chan = Arc::new(Mutex::new(TSChannel {
             sender_count: 1,
             receiver_count: 1,
             max_receiver_index: 1,
-            buf: TSAnyBuffer::<T>::new(bounded, dispatch, start_data_time, speed),
+            buf: TSAnyBuffer::<T>::new(bounded, dispatch, clock),
+            gap_detectors: HashMap::new(),
             #[cfg(feature = "metrics")]
             metrics_mgr,
-        }))));
+        }));
         (
             TSSender {
-                chan,
+                chan: chan.clone(),
                 #[cfg(feature = "metrics")]
                 metrics_idx: sender_metrics_idx,
             },
@@ -531,27 +997,127 @@ impl<T> TSChannel<T> {
     pub fn get_metrics_result(&mut self, clear: bool) -> MetricsResult {
         self.metrics_mgr.get_result(clear)
     }
+
+    #[cfg(feature = "metrics")]
+    fn record_recv(&mut self, recver_index: usize, metrics_idx: usize, count: usize) {
+        if count > 0 {
+            self.metrics_mgr.record(metrics_idx, count);
+        }
+        let dropped = self.buf.take_dropped(recver_index);
+        if dropped > 0 {
+            self.metrics_mgr.record_dropped(metrics_idx, dropped);
+        }
+    }
+}
+
+impl<T: Clone + Sized + GetDataTimeExt> TSChannel<T> {
+    fn recv_event(&mut self, recver_index: usize) -> Option<TSEvent<T>> {
+        if self.is_finished(recver_index) {
+            return Some(TSEvent::Finished);
+        }
+        if !self.gap_detectors.contains_key(&recver_index) {
+            return self.buf.recv(recver_index).map(TSEvent::Item);
+        }
+        // peek at the head item, so that it stays in the buffer for the other receive paths
+        let position = self.buf.playback_position(recver_index);
+        let is_reverse = self.buf.is_reverse(recver_index);
+        let next_data_time = match self.buf.head_data_time(recver_index) {
+            Some(data_time) if is_due_at(data_time, position, is_reverse) => data_time,
+            _ => position,
+        };
+        let detector = self.gap_detectors.get_mut(&recver_index)?;
+        // the data time falls when playing backwards
+        let (gap, step) = match is_reverse {
+            false => (next_data_time - detector.last_data_time, detector.max_gap),
+            true => (detector.last_data_time - next_data_time, -detector.max_gap),
+        };
+        if gap > detector.max_gap {
+            let from = detector.last_data_time;
+            let to = match detector.heartbeat {
+                Some(_) => from + step,
+                None => next_data_time,
+            };
+            detector.last_data_time = to;
+            return Some(TSEvent::Gap { from, to });
+        }
+        let item = self.buf.recv(recver_index)?;
+        let detector = self.gap_detectors.get_mut(&recver_index)?;
+        detector.last_data_time = match is_reverse {
+            false => detector.last_data_time.max(item.get_data_time()),
+            true => detector.last_data_time.min(item.get_data_time()),
+        };
+        Some(TSEvent::Item(item))
+    }
+
+    fn is_finished(&self, recver_index: usize) -> bool {
+        self.buf.is_ended() && self.buf.len(recver_index) == 0
+    }
+
+    /// The real time until the next item is due, `None` if there is no item or the clock is paused
+    fn next_due_in(&self, recver_index: usize) -> Option<std::time::Duration> {
+        let head_data_time = self.buf.head_data_time(recver_index)?;
+        let position = self.buf.playback_position(recver_index);
+        let speed = self.buf.playback_speed(recver_index);
+        let remaining = match self.buf.is_reverse(recver_index) {
+            false => head_data_time - position,
+            true => position - head_data_time,
+        };
+        if remaining <= Duration::zero() {
+            return Some(std::time::Duration::ZERO);
+        }
+        match speed == 0.0 {
+            true => None,
+            false => Some(remaining.to_std().ok()?.div_f64(speed.abs())),
+        }
+    }
 }
 
 pub struct TSSender<T> {
-    chan: NonNull<Mutex<TSChannel<T>>>,
+    chan: Arc<Mutex<TSChannel<T>>>,
     #[cfg(feature = "metrics")]
     metrics_idx: usize,
 }
 
 impl<T: Clone + Sized + GetDataTimeExt> TSSender<T> {
     pub fn send(&self, data: T) {
-        let mut chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let mut chan = self.chan.lock().unwrap();
         #[cfg(feature = "metrics")]
         chan.metrics_mgr.record(self.metrics_idx, 1);
         chan.buf.send(data);
+        chan.buf.get_clock().wake();
     }
 
     pub fn send_items(&self, data: Vec<T>) {
-        let mut chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let mut chan = self.chan.lock().unwrap();
         #[cfg(feature = "metrics")]
         chan.metrics_mgr.record(self.metrics_idx, data.len());
         chan.buf.send_items(data);
+        chan.buf.get_clock().wake();
+    }
+}
+
+impl<T> TSSender<T> {
+    pub fn set_retention(&self, retention: Option<TSRetention>) {
+        let mut chan = self.chan.lock().unwrap();
+        chan.buf.set_retention(retention);
+    }
+
+    /// Replay the retained items once the stream is finished and drained
+    pub fn set_loop(&self, looping: bool) {
+        let mut chan = self.chan.lock().unwrap();
+        chan.buf.set_loop(looping);
+    }
+
+    pub fn get_clock(&self) -> PlaybackClock {
+        let chan = self.chan.lock().unwrap();
+        chan.buf.get_clock().clone()
+    }
+
+    /// Mark the end of the stream, the receivers report `TSEvent::Finished` once drained
+    pub fn finish(&self) {
+        let mut chan = self.chan.lock().unwrap();
+        chan.buf.finish();
+        chan.buf.get_clock().wake();
     }
 }
 
@@ -559,66 +1125,184 @@ impl<T> Clone for TSSender<T> {
     #[cfg(feature = "metrics")]
     #[track_caller]
     fn clone(&self) -> Self {
-        let mut chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let mut chan = self.chan.lock().unwrap();
         chan.sender_count += 1;
         Self {
-            chan: self.chan,
+            chan: self.chan.clone(),
             metrics_idx: chan.new_metrics_index(Location::caller(), HolderType::Sender),
         }
     }
 
     #[cfg(not(feature = "metrics"))]
     fn clone(&self) -> Self {
-        let mut chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let mut chan = self.chan.lock().unwrap();
         chan.sender_count += 1;
-        Self { chan: self.chan }
+        Self {
+            chan: self.chan.clone(),
+        }
     }
 }
 
 impl<T> Drop for TSSender<T> {
     fn drop(&mut self) {
-        let mut chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let mut chan = self.chan.lock().unwrap();
         chan.sender_count -= 1;
-        if chan.sender_count == 0 && chan.receiver_count == 0 {
-            unsafe { ptr::drop_in_place(self.chan.as_ptr()) };
-        }
     }
 }
 
 pub struct TSReceiver<T> {
-    chan: NonNull<Mutex<TSChannel<T>>>,
+    chan: Arc<Mutex<TSChannel<T>>>,
     index: usize,
     #[cfg(feature = "metrics")]
     metrics_idx: usize,
 }
 
 impl<T: Clone + Sized + GetDataTimeExt> TSReceiver<T> {
+    /// A gap yields a heartbeat item when a heartbeat is set and is skipped otherwise
     pub fn recv(&self) -> Option<T> {
-        let mut chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
-        let ret = chan.buf.recv(self.index);
+        loop {
+            match self.recv_event()? {
+                TSEvent::Item(item) => return Some(item),
+                TSEvent::Finished => return None,
+                TSEvent::Gap { to, .. } => {
+                    let chan = self.chan.lock().unwrap();
+                    let detector = chan.gap_detectors.get(&self.index);
+                    if let Some(heartbeat) = detector.and_then(|d| d.heartbeat.as_ref()) {
+                        return Some(heartbeat(to));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Receive the next due item, or the gap before it
+    pub fn recv_event(&self) -> Option<TSEvent<T>> {
+        let mut chan = self.chan.lock().unwrap();
+        let ret = chan.recv_event(self.index);
         #[cfg(feature = "metrics")]
-        if ret.is_some() {
-            chan.metrics_mgr.record(self.metrics_idx, 1);
+        match ret {
+            Some(TSEvent::Item(_)) => chan.record_recv(self.index, self.metrics_idx, 1),
+            Some(TSEvent::Gap { .. }) => chan.metrics_mgr.record_gap(self.metrics_idx, 1),
+            _ => chan.record_recv(self.index, self.metrics_idx, 0),
         }
         ret
     }
 
+    /// Block until the next item is due, `None` once the stream is finished
+    pub fn recv_wait(&self) -> Option<T> {
+        let clock = self.get_clock();
+        loop {
+            let generation = clock.wake_generation();
+            if let Some(item) = self.recv() {
+                return Some(item);
+            }
+            if self.is_finished() {
+                return None;
+            }
+            clock.wait_wake(generation, self.next_due_in());
+        }
+    }
+
+    pub async fn recv_wait_async(&self) -> Option<T> {
+        let clock = self.get_clock();
+        loop {
+            let notified = clock.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if let Some(item) = self.recv() {
+                return Some(item);
+            }
+            if self.is_finished() {
+                return None;
+            }
+            match self.next_due_in() {
+                Some(due_in) => {
+                    tokio::select! {
+                        _ = notified => (),
+                        _ = tokio::time::sleep(due_in) => (),
+                    }
+                }
+                None => notified.await,
+            }
+        }
+    }
+
+    pub fn next_due_in(&self) -> Option<std::time::Duration> {
+        let chan = self.chan.lock().unwrap();
+        chan.next_due_in(self.index)
+    }
+
+    /// The oldest and newest data time of the items not yet received
+    pub fn data_span(&self) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        let chan = self.chan.lock().unwrap();
+        chan.buf.data_span(self.index)
+    }
+
+    /// Whether the sender finished the stream and all of its items have been received
+    pub fn is_finished(&self) -> bool {
+        let chan = self.chan.lock().unwrap();
+        chan.is_finished(self.index)
+    }
+
+    /// Detect the gaps between the items that are longer than the maximum gap
+    pub fn set_gap_detection(&self, max_gap: Option<Duration>) {
+        self.set_gap_detector(max_gap, None);
+    }
+
+    /// Like `set_gap_detection`, and `recv` fills every gap with items made by the heartbeat
+    pub fn set_heartbeat<F: Fn(NaiveDateTime) -> T + Send + 'static>(
+        &self,
+        max_gap: Duration,
+        f: F,
+    ) {
+        self.set_gap_detector(Some(max_gap), Some(Box::new(f)));
+    }
+
+    fn set_gap_detector(
+        &self,
+        max_gap: Option<Duration>,
+        heartbeat: Option<Box<dyn Fn(NaiveDateTime) -> T + Send>>,
+    ) {
+        let mut chan = self.chan.lock().unwrap();
+        let last_data_time = chan.buf.playback_position(self.index);
+        chan.gap_detectors.remove(&self.index);
+        if let Some(max_gap) = max_gap {
+            let detector = TSGapDetector {
+                max_gap,
+                last_data_time,
+                heartbeat,
+            };
+            chan.gap_detectors.insert(self.index, detector);
+        }
+    }
+
     pub fn recv_items(&self, count: usize) -> Vec<T> {
-        let mut chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let mut chan = self.chan.lock().unwrap();
         let ret = chan.buf.recv_count(self.index, count, true);
         #[cfg(feature = "metrics")]
-        if ret.len() > 0 {
-            chan.metrics_mgr.record(self.metrics_idx, ret.len());
-        }
+        chan.record_recv(self.index, self.metrics_idx, ret.len());
         ret
     }
 
     pub fn recv_items_weak(&self, max_count: usize) -> Vec<T> {
-        let mut chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let mut chan = self.chan.lock().unwrap();
         let ret = chan.buf.recv_count(self.index, max_count, false);
         #[cfg(feature = "metrics")]
-        if ret.len() > 0 {
-            chan.metrics_mgr.record(self.metrics_idx, ret.len());
+        chan.record_recv(self.index, self.metrics_idx, ret.len());
+        ret
+    }
+
+    /// Receive the most recent due item, skipping (and dropping) the earlier due items
+    pub fn recv_latest_due(&self) -> Option<T> {
+        let mut chan = self.chan.lock().unwrap();
+        let count = chan.buf.len(self.index);
+        let mut items = chan.buf.recv_count(self.index, count, false);
+        let ret = items.pop();
+        #[cfg(feature = "metrics")]
+        {
+            let skipped = items.len();
+            chan.record_recv(self.index, self.metrics_idx, usize::from(ret.is_some()));
+            chan.metrics_mgr.record_dropped(self.metrics_idx, skipped);
         }
         ret
     }
@@ -626,20 +1310,44 @@ impl<T: Clone + Sized + GetDataTimeExt> TSReceiver<T> {
 
 impl<T> TSReceiver<T> {
     pub fn len(&self) -> usize {
-        let chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let chan = self.chan.lock().unwrap();
         chan.buf.len(self.index)
     }
 
     pub fn is_empty(&self) -> bool {
-        let chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let chan = self.chan.lock().unwrap();
         chan.buf.len(self.index) == 0
     }
 
+    pub fn get_clock(&self) -> PlaybackClock {
+        let chan = self.chan.lock().unwrap();
+        chan.buf.get_clock().clone()
+    }
+
+    /// Delay this receiver by the offset and scale its speed, panics if not in dispatch mode
+    pub fn set_view(&self, offset: Duration, speed: f64) {
+        let is_dispatch = {
+            let mut chan = self.chan.lock().unwrap();
+            let is_dispatch = chan.buf.set_receiver_view(self.index, offset, speed);
+            chan.buf.get_clock().wake();
+            is_dispatch
+        };
+        assert!(is_dispatch, "set_view needs a dispatch time series channel");
+    }
+
+    /// The data time this receiver has played to
+    pub fn playback_position(&self) -> NaiveDateTime {
+        let chan = self.chan.lock().unwrap();
+        chan.buf.playback_position(self.index)
+    }
+
     pub fn get_observer(&self) -> TSObserver<T> {
-        let mut chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let mut chan = self.chan.lock().unwrap();
         chan.receiver_count += 1;
         chan.max_receiver_index += 1;
-        TSObserver { chan: self.chan }
+        TSObserver {
+            chan: self.chan.clone(),
+        }
     }
 }
 
@@ -647,13 +1355,13 @@ impl<T> Clone for TSReceiver<T> {
     #[cfg(feature = "metrics")]
     #[track_caller]
     fn clone(&self) -> Self {
-        let mut chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let mut chan = self.chan.lock().unwrap();
         chan.receiver_count += 1;
         let index = chan.max_receiver_index;
         chan.max_receiver_index += 1;
         chan.buf.new_receiver(index);
         Self {
-            chan: self.chan,
+            chan: self.chan.clone(),
             index,
             metrics_idx: chan.new_metrics_index(Location::caller(), HolderType::Receiver),
         }
@@ -661,13 +1369,13 @@ impl<T> Clone for TSReceiver<T> {
 
     #[cfg(not(feature = "metrics"))]
     fn clone(&self) -> Self {
-        let mut chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let mut chan = self.chan.lock().unwrap();
         chan.receiver_count += 1;
         let index = chan.max_receiver_index;
         chan.max_receiver_index += 1;
         chan.buf.new_receiver(index);
         Self {
-            chan: self.chan,
+            chan: self.chan.clone(),
             index,
         }
     }
@@ -675,47 +1383,71 @@ impl<T> Clone for TSReceiver<T> {
 
 impl<T> Drop for TSReceiver<T> {
     fn drop(&mut self) {
-        let mut chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let mut chan = self.chan.lock().unwrap();
         chan.buf.drop_receiver(self.index);
+        chan.gap_detectors.remove(&self.index);
         chan.receiver_count -= 1;
-        if chan.receiver_count == 0 && chan.sender_count == 0 {
-            unsafe { ptr::drop_in_place(self.chan.as_ptr()) };
-        }
     }
 }
 
 pub struct TSObserver<T> {
-    chan: NonNull<Mutex<TSChannel<T>>>,
+    chan: Arc<Mutex<TSChannel<T>>>,
 }
 
 impl<T: Clone + Sized + GetDataTimeExt> TSObserver<T> {
     pub fn query_items(&self, start: usize, end: Option<usize>) -> Vec<T> {
-        let chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let chan = self.chan.lock().unwrap();
         chan.buf.query_items(start, end)
     }
+
+    pub fn next_due_in(&self) -> Option<std::time::Duration> {
+        let chan = self.chan.lock().unwrap();
+        chan.next_due_in(usize::MAX)
+    }
+
+    pub fn data_span(&self) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        let chan = self.chan.lock().unwrap();
+        chan.buf.data_span(usize::MAX)
+    }
+
+    /// Whether the sender finished the stream and the buffer is drained
+    pub fn is_finished(&self) -> bool {
+        let chan = self.chan.lock().unwrap();
+        chan.is_finished(usize::MAX)
+    }
 }
 
 impl<T> TSObserver<T> {
     pub fn len(&self) -> usize {
-        let chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let chan = self.chan.lock().unwrap();
         chan.buf.len(usize::MAX)
     }
 
     pub fn is_empty(&self) -> bool {
-        let chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let chan = self.chan.lock().unwrap();
         chan.buf.len(usize::MAX) == 0
     }
 
+    pub fn get_clock(&self) -> PlaybackClock {
+        let chan = self.chan.lock().unwrap();
+        chan.buf.get_clock().clone()
+    }
+
+    pub fn playback_position(&self) -> NaiveDateTime {
+        let chan = self.chan.lock().unwrap();
+        chan.buf.playback_position(usize::MAX)
+    }
+
     #[cfg(feature = "metrics")]
     #[track_caller]
     pub fn get_receiver(&self) -> TSReceiver<T> {
-        let mut chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let mut chan = self.chan.lock().unwrap();
         chan.receiver_count += 1;
         let index = chan.max_receiver_index;
         chan.max_receiver_index += 1;
         chan.buf.new_receiver(index);
         TSReceiver {
-            chan: self.chan,
+            chan: self.chan.clone(),
             index,
             metrics_idx: chan.new_metrics_index(Location::caller(), HolderType::Receiver),
         }
@@ -723,30 +1455,27 @@ impl<T> TSObserver<T> {
 
     #[cfg(not(feature = "metrics"))]
     pub fn get_receiver(&self) -> TSReceiver<T> {
-        let mut chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let mut chan = self.chan.lock().unwrap();
         chan.receiver_count += 1;
         let index = chan.max_receiver_index;
         chan.max_receiver_index += 1;
         chan.buf.new_receiver(index);
         TSReceiver {
-            chan: self.chan,
+            chan: self.chan.clone(),
             index,
         }
     }
 
     #[cfg(feature = "metrics")]
     pub fn get_metrics_result(&mut self, clear: bool) -> MetricsResult {
-        let mut chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let mut chan = self.chan.lock().unwrap();
         chan.get_metrics_result(clear)
     }
 }
 
 impl<T> Drop for TSObserver<T> {
     fn drop(&mut self) {
-        let mut chan = unsafe { self.chan.clone().as_mut().lock().unwrap() };
+        let mut chan = self.chan.lock().unwrap();
         chan.receiver_count -= 1;
-        if chan.receiver_count == 0 && chan.sender_count == 0 {
-            unsafe { ptr::drop_in_place(self.chan.as_ptr()) };
-        }
     }
 }
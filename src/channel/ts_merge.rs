@@ -0,0 +1,98 @@
+use super::time_series::{GetDataTimeExt, TSReceiver};
+use std::time::{Duration, Instant};
+
+struct TSMergeInput<T> {
+    source: Box<dyn FnMut() -> Option<T> + Send>,
+    head: Option<T>,
+    idle_timeout: Duration,
+    last_active: Instant,
+}
+
+impl<T> TSMergeInput<T> {
+    fn fill_head(&mut self) {
+        if self.head.is_none() {
+            self.head = (self.source)();
+            if self.head.is_some() {
+                self.last_active = Instant::now();
+            }
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.last_active.elapsed() >= self.idle_timeout
+    }
+}
+
+/// Merges multiple time series receivers into one stream ordered by data time
+pub struct TSMerge<T> {
+    inputs: Vec<TSMergeInput<T>>,
+}
+
+impl<T: GetDataTimeExt> TSMerge<T> {
+    pub fn new() -> Self {
+        Self { inputs: vec![] }
+    }
+
+    pub fn add_receiver(&mut self, receiver: TSReceiver<T>, idle_timeout: Duration)
+    where
+        T: Clone + Send + 'static,
+    {
+        self.add_source(move || receiver.recv(), idle_timeout);
+    }
+
+    pub fn add_receiver_map<U, F>(&mut self, receiver: TSReceiver<U>, idle_timeout: Duration, f: F)
+    where
+        U: Clone + GetDataTimeExt + Send + 'static,
+        F: Fn(U) -> T + Send + 'static,
+    {
+        self.add_source(move || receiver.recv().map(&f), idle_timeout);
+    }
+
+    fn add_source<F: FnMut() -> Option<T> + Send + 'static>(
+        &mut self,
+        source: F,
+        idle_timeout: Duration,
+    ) {
+        self.inputs.push(TSMergeInput {
+            source: Box::new(source),
+            head: None,
+            idle_timeout,
+            last_active: Instant::now(),
+        });
+    }
+
+    pub fn try_recv(&mut self) -> Option<T> {
+        for input in self.inputs.iter_mut() {
+            input.fill_head();
+        }
+        if self
+            .inputs
+            .iter()
+            .any(|input| input.head.is_none() && !input.is_idle())
+        {
+            return None;
+        }
+        let min_index = self
+            .inputs
+            .iter()
+            .enumerate()
+            .filter_map(|(index, input)| Some((index, input.head.as_ref()?.get_data_time())))
+            .min_by_key(|(_, data_time)| *data_time)
+            .map(|(index, _)| index)?;
+        self.inputs[min_index].head.take()
+    }
+
+    pub fn recv_items(&mut self) -> Vec<T> {
+        let mut items = vec![];
+        while let Some(item) = self.try_recv() {
+            items.push(item);
+        }
+        items
+    }
+}
+
+impl<T: GetDataTimeExt> Default for TSMerge<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,217 @@
+use super::time_series::{GetDataTimeExt, TSReceiver};
+use crate::utils::time_util::align_data_time;
+use chrono::{Duration, NaiveDateTime};
+use std::collections::VecDeque;
+
+/// Linear interpolation between two items, used by `TSResampler::linear`
+pub trait Interpolate {
+    /// `ratio` is in `[0, 1]`, 0 is `self` and 1 is `next`
+    fn interpolate(&self, next: &Self, ratio: f64) -> Self;
+}
+
+#[derive(Debug, Clone)]
+pub struct Sample<V> {
+    pub time: NaiveDateTime,
+    pub value: V,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PointKind {
+    LastValueHold,
+    Nearest,
+    Linear,
+}
+
+type SampleFn<T, A> = Box<dyn Fn(&T, &T, f64) -> A + Send>;
+type FoldFn<T, A> = Box<dyn Fn(&mut A, T) + Send>;
+
+enum Strategy<T, A> {
+    Point {
+        kind: PointKind,
+        sample: SampleFn<T, A>,
+    },
+    Aggregate {
+        init: Box<dyn Fn() -> A + Send>,
+        fold: FoldFn<T, A>,
+    },
+}
+
+/// Resamples the items of a time series receiver onto a uniform data time grid
+pub struct TSResampler<T, A> {
+    receiver: TSReceiver<T>,
+    interval: Duration,
+    strategy: Strategy<T, A>,
+    items: VecDeque<T>,
+    next_time: Option<NaiveDateTime>,
+}
+
+impl<T: Clone + Sized + GetDataTimeExt + 'static> TSResampler<T, T> {
+    /// Every grid point takes the last item at or before it
+    pub fn last_value_hold(receiver: TSReceiver<T>, interval: Duration) -> Self {
+        Self::new(
+            receiver,
+            interval,
+            PointKind::LastValueHold,
+            |prev, _, _| prev.clone(),
+        )
+    }
+
+    /// Every grid point takes the item closest to it in data time
+    pub fn nearest(receiver: TSReceiver<T>, interval: Duration) -> Self {
+        Self::new(
+            receiver,
+            interval,
+            PointKind::Nearest,
+            |prev, next, ratio| match ratio <= 0.5 {
+                true => prev.clone(),
+                false => next.clone(),
+            },
+        )
+    }
+}
+
+impl<T: Clone + Sized + GetDataTimeExt + Interpolate + 'static> TSResampler<T, T> {
+    /// Every grid point interpolates between the items around it
+    pub fn linear(receiver: TSReceiver<T>, interval: Duration) -> Self {
+        Self::new(
+            receiver,
+            interval,
+            PointKind::Linear,
+            |prev, next, ratio| prev.interpolate(next, ratio),
+        )
+    }
+}
+
+impl<T: Clone + Sized + GetDataTimeExt, A> TSResampler<T, A> {
+    /// Fold the items of every grid bucket into one value
+    pub fn aggregate<I, F>(receiver: TSReceiver<T>, interval: Duration, init: I, fold: F) -> Self
+    where
+        I: Fn() -> A + Send + 'static,
+        F: Fn(&mut A, T) + Send + 'static,
+    {
+        assert!(
+            interval > Duration::zero(),
+            "the resample interval must be positive"
+        );
+        Self {
+            receiver,
+            interval,
+            strategy: Strategy::Aggregate {
+                init: Box::new(init),
+                fold: Box::new(fold),
+            },
+            items: VecDeque::new(),
+            next_time: None,
+        }
+    }
+
+    fn new<F>(receiver: TSReceiver<T>, interval: Duration, kind: PointKind, sample: F) -> Self
+    where
+        F: Fn(&T, &T, f64) -> A + Send + 'static,
+    {
+        assert!(
+            interval > Duration::zero(),
+            "the resample interval must be positive"
+        );
+        Self {
+            receiver,
+            interval,
+            strategy: Strategy::Point {
+                kind,
+                sample: Box::new(sample),
+            },
+            items: VecDeque::new(),
+            next_time: None,
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Option<Sample<A>> {
+        while let Some(item) = self.receiver.recv() {
+            self.items.push_back(item);
+        }
+        let time = match self.next_time {
+            Some(time) => time,
+            None => {
+                let first_data_time = self.items.front()?.get_data_time();
+                let time = self.align(first_data_time);
+                match (&self.strategy, time < first_data_time) {
+                    (Strategy::Point { .. }, true) => time + self.interval,
+                    _ => time,
+                }
+            }
+        };
+        self.next_time = Some(time);
+        let position = self.receiver.playback_position();
+        let value = match &self.strategy {
+            Strategy::Point { kind, sample } => {
+                let index = self
+                    .items
+                    .partition_point(|item| item.get_data_time() <= time);
+                let prev = self.items.get(index.checked_sub(1)?)?;
+                let next = self.items.get(index);
+                let prev_data_time = prev.get_data_time();
+                let is_ready = match (kind, next) {
+                    (PointKind::LastValueHold, _) => position >= time,
+                    (PointKind::Nearest, None) => position >= time + (time - prev_data_time),
+                    (PointKind::Linear, None) => prev_data_time == time && position >= time,
+                    (_, Some(_)) => true,
+                };
+                if !is_ready {
+                    return None;
+                }
+                let value = match next {
+                    Some(next) if *kind != PointKind::LastValueHold => {
+                        let span = (next.get_data_time() - prev_data_time).num_nanoseconds();
+                        let offset = (time - prev_data_time).num_nanoseconds();
+                        let ratio = match (offset, span) {
+                            (Some(offset), Some(span)) if span > 0 => offset as f64 / span as f64,
+                            _ => 0.0,
+                        };
+                        sample(prev, next, ratio)
+                    }
+                    _ => sample(prev, prev, 0.0),
+                };
+                self.items.drain(..index - 1);
+                value
+            }
+            Strategy::Aggregate { init, fold } => {
+                let end = time + self.interval;
+                if position < end {
+                    return None;
+                }
+                let mut acc = init();
+                while let Some(item) = self.items.pop_front() {
+                    let data_time = item.get_data_time();
+                    if data_time >= end {
+                        self.items.push_front(item);
+                        break;
+                    }
+                    if data_time >= time {
+                        fold(&mut acc, item);
+                    }
+                }
+                acc
+            }
+        };
+        self.next_time = Some(time + self.interval);
+        Some(Sample { time, value })
+    }
+
+    pub fn recv_items(&mut self) -> Vec<Sample<A>> {
+        let mut samples = vec![];
+        while let Some(sample) = self.try_recv() {
+            samples.push(sample);
+        }
+        samples
+    }
+
+    fn align(&self, data_time: NaiveDateTime) -> NaiveDateTime {
+        align_data_time(data_time, self.interval)
+    }
+}
+
+impl<T, A> TSResampler<T, A> {
+    pub fn get_receiver(&self) -> &TSReceiver<T> {
+        &self.receiver
+    }
+}
@@ -0,0 +1,125 @@
+use super::time_series::{GetDataTimeExt, TSReceiver};
+use crate::utils::time_util::align_data_time;
+use chrono::{Duration, NaiveDateTime};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone)]
+pub struct Window<A> {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub items: A,
+}
+
+/// Groups the items of a time series receiver into tumbling or sliding windows by data time
+pub struct TSWindow<T, A> {
+    receiver: TSReceiver<T>,
+    size: Duration,
+    slide: Duration,
+    allowed_lateness: Duration,
+    init: Box<dyn Fn() -> A + Send>,
+    fold: Box<dyn Fn(&mut A, T) + Send>,
+    windows: BTreeMap<NaiveDateTime, A>,
+    closed_until: Option<NaiveDateTime>,
+}
+
+impl<T: Clone + Sized + GetDataTimeExt + 'static> TSWindow<T, Vec<T>> {
+    pub fn tumbling(receiver: TSReceiver<T>, size: Duration) -> Self {
+        Self::sliding(receiver, size, size)
+    }
+
+    pub fn sliding(receiver: TSReceiver<T>, size: Duration, slide: Duration) -> Self {
+        Self::sliding_fold(receiver, size, slide, Vec::new, |items, item| {
+            items.push(item)
+        })
+    }
+}
+
+impl<T: Clone + Sized + GetDataTimeExt, A> TSWindow<T, A> {
+    pub fn tumbling_fold<I, F>(receiver: TSReceiver<T>, size: Duration, init: I, fold: F) -> Self
+    where
+        I: Fn() -> A + Send + 'static,
+        F: Fn(&mut A, T) + Send + 'static,
+    {
+        Self::sliding_fold(receiver, size, size, init, fold)
+    }
+
+    pub fn sliding_fold<I, F>(
+        receiver: TSReceiver<T>,
+        size: Duration,
+        slide: Duration,
+        init: I,
+        fold: F,
+    ) -> Self
+    where
+        I: Fn() -> A + Send + 'static,
+        F: Fn(&mut A, T) + Send + 'static,
+    {
+        assert!(
+            slide > Duration::zero(),
+            "the window slide must be positive"
+        );
+        assert!(
+            size >= slide,
+            "the window size must not be smaller than the slide"
+        );
+        Self {
+            receiver,
+            size,
+            slide,
+            allowed_lateness: Duration::zero(),
+            init: Box::new(init),
+            fold: Box::new(fold),
+            windows: BTreeMap::new(),
+            closed_until: None,
+        }
+    }
+
+    pub fn set_allowed_lateness(&mut self, allowed_lateness: Duration) {
+        self.allowed_lateness = allowed_lateness;
+    }
+
+    pub fn try_recv(&mut self) -> Option<Window<A>> {
+        while let Some(item) = self.receiver.recv() {
+            self.add_item(item);
+        }
+        let position = self.receiver.playback_position();
+        let (&start, _) = self.windows.iter().next()?;
+        let end = start + self.size;
+        if end + self.allowed_lateness > position {
+            return None;
+        }
+        let items = self.windows.remove(&start)?;
+        self.closed_until = Some(self.closed_until.map_or(end, |closed| closed.max(end)));
+        Some(Window { start, end, items })
+    }
+
+    pub fn recv_items(&mut self) -> Vec<Window<A>> {
+        let mut windows = vec![];
+        while let Some(window) = self.try_recv() {
+            windows.push(window);
+        }
+        windows
+    }
+
+    fn add_item(&mut self, item: T) {
+        let data_time = item.get_data_time();
+        let mut start = align_data_time(data_time, self.slide);
+        while start + self.size > data_time {
+            let is_closed = match self.closed_until {
+                Some(closed_until) => start + self.size <= closed_until,
+                None => false,
+            };
+            if !is_closed {
+                let acc = self.windows.entry(start).or_insert_with(|| (self.init)());
+                (self.fold)(acc, item.clone());
+            }
+            start -= self.slide;
+        }
+    }
+}
+
+impl<T, A> TSWindow<T, A> {
+    pub fn get_receiver(&self) -> &TSReceiver<T> {
+        &self.receiver
+    }
+}
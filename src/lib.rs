@@ -5,18 +5,46 @@ pub mod test;
 use std::panic::Location;
 
 pub mod channel;
+#[cfg(feature = "replay")]
+pub mod replay;
 pub mod utils;
 
-pub use channel::asynchronous::{UnboundedReceiverAsync, UnboundedSenderAsync};
-pub use channel::bidirectional::{BDUnbRequester, BDUnbResponder};
-pub use channel::bidirectional::{BDUnbRequesterAsync, BDUnbResponderAsync};
-pub use channel::time_series::{GetDataTimeExt, TSObserver, TSReceiver, TSSender};
+pub use channel::asynchronous::{
+    ReceiverAsync, RecvError, SenderAsync, UnboundedReceiverAsync, UnboundedSenderAsync,
+};
+pub use channel::bd_serve::BDServer;
+pub use channel::bidirectional::{
+    BDError, BDGather, BDQuorum, BDUnbRequester, BDUnbResponder, PendingGather, ReplyHandle,
+    RequestTicket, ResponderId, ResponseStream,
+};
+pub use channel::bidirectional::{
+    BDUnbRequesterAsync, BDUnbResponderAsync, ReplyHandleAsync, ResponseStreamAsync,
+};
+pub use channel::frame_pacer::{TSFramePacer, TSFramePacerError};
+pub use channel::playback_clock::PlaybackClock;
+pub use channel::time_series::{
+    GetDataTimeExt, TSEvent, TSObserver, TSReceiver, TSRetention, TSSender,
+};
+pub use channel::ts_merge::TSMerge;
+pub use channel::ts_resample::{Interpolate, Sample, TSResampler};
+pub use channel::ts_window::{TSWindow, Window};
 pub use channel::{Observer, Receiver, Sender};
+#[cfg(feature = "derive")]
+pub use channel_derive::GetDataTime;
 
-use channel::asynchronous::UnboundedBufferAsync;
+/// The paths the code generated by `GetDataTime` refers to, not a public API
+#[cfg(feature = "derive")]
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::GetDataTimeExt;
+    pub use chrono::NaiveDateTime;
+}
+
+use channel::asynchronous::{ChannelAsync, UnboundedBufferAsync};
 use channel::bidirectional::{BDUnbBuffer, BDUnbBufferAsync};
 use channel::{time_series::TSChannel, Channel};
 use chrono::NaiveDateTime;
+use std::future::Future;
 
 #[cfg(not(feature = "metrics"))]
 pub fn new<T: Clone + Send>(bounded: Option<usize>, dispatch: bool) -> (Sender<T>, Receiver<T>) {
@@ -36,7 +64,8 @@ pub fn new_time_series<T: Clone + Send + GetDataTimeExt>(
     start_data_time: NaiveDateTime,
     speed: f64,
 ) -> (TSSender<T>, TSReceiver<T>) {
-    TSChannel::new(bounded, dispatch, start_data_time, speed)
+    let clock = PlaybackClock::new(start_data_time, speed);
+    TSChannel::new(bounded, dispatch, clock)
 }
 
 #[cfg(feature = "metrics")]
@@ -47,24 +76,118 @@ pub fn new_time_series<T: Clone + Send + GetDataTimeExt>(
     start_data_time: NaiveDateTime,
     speed: f64,
 ) -> (TSSender<T>, TSReceiver<T>) {
-    TSChannel::new(
-        bounded,
-        dispatch,
-        start_data_time,
-        speed,
-        Location::caller(),
-    )
+    let clock = PlaybackClock::new(start_data_time, speed);
+    TSChannel::new(bounded, dispatch, clock, Location::caller())
+}
+
+#[cfg(not(feature = "metrics"))]
+pub fn new_time_series_with_clock<T: Clone + Send + GetDataTimeExt>(
+    bounded: Option<usize>,
+    dispatch: bool,
+    clock: &PlaybackClock,
+) -> (TSSender<T>, TSReceiver<T>) {
+    TSChannel::new(bounded, dispatch, clock.clone())
+}
+
+#[cfg(feature = "metrics")]
+#[track_caller]
+pub fn new_time_series_with_clock<T: Clone + Send + GetDataTimeExt>(
+    bounded: Option<usize>,
+    dispatch: bool,
+    clock: &PlaybackClock,
+) -> (TSSender<T>, TSReceiver<T>) {
+    TSChannel::new(bounded, dispatch, clock.clone(), Location::caller())
 }
 
 pub fn new_unbounded_bidirectional<T1, T2>() -> (BDUnbRequester<T1, T2>, BDUnbResponder<T1, T2>) {
-    BDUnbBuffer::new()
+    BDUnbBuffer::new(None)
 }
 
 pub fn new_unbounded_bidirectional_async<T1, T2>(
 ) -> (BDUnbRequesterAsync<T1, T2>, BDUnbResponderAsync<T1, T2>) {
-    BDUnbBufferAsync::new()
+    BDUnbBufferAsync::new(None)
+}
+
+/// At most `bounded` requests are outstanding, queued or taken but not replied yet
+pub fn new_bounded_bidirectional<T1, T2>(
+    bounded: usize,
+) -> (BDUnbRequester<T1, T2>, BDUnbResponder<T1, T2>) {
+    BDUnbBuffer::new(Some(bounded))
+}
+
+/// At most `bounded` requests are outstanding, queued or taken but not replied yet
+pub fn new_bounded_bidirectional_async<T1, T2>(
+    bounded: usize,
+) -> (BDUnbRequesterAsync<T1, T2>, BDUnbResponderAsync<T1, T2>) {
+    BDUnbBufferAsync::new(Some(bounded))
+}
+
+/// At most `concurrency` requests at a time, a panicking handler fails with `BDError::Panicked`
+#[cfg(not(feature = "metrics"))]
+pub fn serve<T1, T2, F, Fut>(
+    responder: BDUnbResponderAsync<T1, T2>,
+    concurrency: usize,
+    handler: F,
+) -> BDServer
+where
+    T1: Send + 'static,
+    T2: Send + 'static,
+    F: Fn(T1) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = T2> + Send + 'static,
+{
+    BDServer::new(responder, concurrency, handler)
+}
+
+/// At most `concurrency` requests at a time, a panicking handler fails with `BDError::Panicked`
+#[cfg(feature = "metrics")]
+#[track_caller]
+pub fn serve<T1, T2, F, Fut>(
+    responder: BDUnbResponderAsync<T1, T2>,
+    concurrency: usize,
+    handler: F,
+) -> BDServer
+where
+    T1: Send + 'static,
+    T2: Send + 'static,
+    F: Fn(T1) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = T2> + Send + 'static,
+{
+    BDServer::new(responder, concurrency, handler, Location::caller())
+}
+
+/// A request failed by the service reports `BDError::NoResponse`
+#[cfg(all(feature = "tower", not(feature = "metrics")))]
+pub fn serve_service<T1, T2, S>(responder: BDUnbResponderAsync<T1, T2>, service: S) -> BDServer
+where
+    T1: Send + 'static,
+    T2: Send + 'static,
+    S: tower_service::Service<T1, Response = T2> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    BDServer::with_service(responder, service)
+}
+
+/// A request failed by the service reports `BDError::NoResponse`
+#[cfg(all(feature = "tower", feature = "metrics"))]
+#[track_caller]
+pub fn serve_service<T1, T2, S>(responder: BDUnbResponderAsync<T1, T2>, service: S) -> BDServer
+where
+    T1: Send + 'static,
+    T2: Send + 'static,
+    S: tower_service::Service<T1, Response = T2> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    BDServer::with_service(responder, service, Location::caller())
 }
 
 pub fn new_unbounded_async<T1>() -> (UnboundedSenderAsync<T1>, UnboundedReceiverAsync<T1>) {
     UnboundedBufferAsync::new()
 }
+
+/// The async counterpart of `new`, every send wakes all the receivers waiting on the channel
+pub fn new_async<T: Clone + Send>(
+    bounded: Option<usize>,
+    dispatch: bool,
+) -> (SenderAsync<T>, ReceiverAsync<T>) {
+    ChannelAsync::new(bounded, dispatch)
+}
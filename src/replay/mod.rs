@@ -0,0 +1,36 @@
+pub mod recorder;
+pub mod source;
+
+pub use recorder::Recorder;
+pub use source::ReplaySource;
+
+use std::io;
+
+/// The maximum length of a binary record, longer records are rejected when reading and writing
+pub const MAX_BINARY_RECORD_LEN: usize = 16 * 1024 * 1024;
+
+/// File format of the recorded time series records
+#[derive(Debug, Clone)]
+pub enum ReplayFormat {
+    Jsonl,
+    /// `time_format` is the chrono format of the `time_column` when reading
+    Csv {
+        time_column: String,
+        time_format: Option<String>,
+    },
+    /// Each record is prefixed by its length as a little endian u32
+    Binary,
+}
+
+impl ReplayFormat {
+    pub fn csv(time_column: &str) -> Self {
+        ReplayFormat::Csv {
+            time_column: time_column.to_string(),
+            time_format: None,
+        }
+    }
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
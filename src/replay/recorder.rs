@@ -0,0 +1,92 @@
+use super::{invalid_data, ReplayFormat, MAX_BINARY_RECORD_LEN};
+use crate::channel::time_series::{GetDataTimeExt, TSReceiver};
+use crate::channel::Receiver;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+enum RecordWriter {
+    Jsonl(BufWriter<Box<dyn Write>>),
+    Csv(Box<csv::Writer<Box<dyn Write>>>),
+    Binary(BufWriter<Box<dyn Write>>),
+}
+
+/// Writes records in one of the replay formats, can tee the items of a receiver
+pub struct Recorder<T> {
+    writer: RecordWriter,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Serialize> Recorder<T> {
+    pub fn create<P: AsRef<Path>>(path: P, format: ReplayFormat) -> io::Result<Self> {
+        Ok(Self::from_writer(File::create(path)?, format))
+    }
+
+    pub fn from_writer<W: Write + 'static>(writer: W, format: ReplayFormat) -> Self {
+        let writer: Box<dyn Write> = Box::new(writer);
+        let writer = match format {
+            ReplayFormat::Jsonl => RecordWriter::Jsonl(BufWriter::new(writer)),
+            ReplayFormat::Csv { .. } => {
+                RecordWriter::Csv(Box::new(csv::Writer::from_writer(writer)))
+            }
+            ReplayFormat::Binary => RecordWriter::Binary(BufWriter::new(writer)),
+        };
+        Self {
+            writer,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn record(&mut self, item: &T) -> io::Result<()> {
+        match &mut self.writer {
+            RecordWriter::Jsonl(writer) => {
+                serde_json::to_writer(&mut *writer, item)?;
+                writer.write_all(b"\n")
+            }
+            RecordWriter::Csv(writer) => Ok(writer.serialize(item)?),
+            RecordWriter::Binary(writer) => {
+                let buf = bincode::serialize(item).map_err(invalid_data)?;
+                if buf.len() > MAX_BINARY_RECORD_LEN {
+                    return Err(invalid_data("binary record is too long"));
+                }
+                let len = buf.len() as u32;
+                writer.write_all(&len.to_le_bytes())?;
+                writer.write_all(&buf)
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match &mut self.writer {
+            RecordWriter::Jsonl(writer) => writer.flush(),
+            RecordWriter::Csv(writer) => writer.flush(),
+            RecordWriter::Binary(writer) => writer.flush(),
+        }
+    }
+}
+
+impl<T: Serialize + Clone + Sized> Recorder<T> {
+    /// Receive all available items, record them and hand them back
+    pub fn tee(&mut self, receiver: &Receiver<T>) -> io::Result<Vec<T>> {
+        let mut items = vec![];
+        while let Some(item) = receiver.recv() {
+            self.record(&item)?;
+            items.push(item);
+        }
+        Ok(items)
+    }
+}
+
+impl<T: Serialize + Clone + Sized + GetDataTimeExt> Recorder<T> {
+    /// Receive all due items, record them and hand them back
+    pub fn tee_ts(&mut self, receiver: &TSReceiver<T>) -> io::Result<Vec<T>> {
+        let mut items = vec![];
+        while let Some(item) = receiver.recv() {
+            self.record(&item)?;
+            items.push(item);
+        }
+        Ok(items)
+    }
+}
@@ -0,0 +1,153 @@
+use super::{invalid_data, ReplayFormat, MAX_BINARY_RECORD_LEN};
+use crate::channel::time_series::{GetDataTimeExt, TSSender};
+use chrono::{Duration, NaiveDateTime};
+use serde::de::DeserializeOwned;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+const ISO_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+enum RecordReader {
+    Jsonl(BufReader<Box<dyn Read>>),
+    Csv {
+        reader: csv::Reader<Box<dyn Read>>,
+        headers: csv::StringRecord,
+        time_index: usize,
+        time_format: Option<String>,
+    },
+    Binary(BufReader<Box<dyn Read>>),
+}
+
+/// Streams the records of a recorded file, reading one record at a time
+pub struct ReplaySource<T> {
+    reader: RecordReader,
+    pending: Option<T>,
+}
+
+impl<T: DeserializeOwned> ReplaySource<T> {
+    pub fn open<P: AsRef<Path>>(path: P, format: ReplayFormat) -> io::Result<Self> {
+        Self::from_reader(File::open(path)?, format)
+    }
+
+    pub fn from_reader<R: Read + 'static>(reader: R, format: ReplayFormat) -> io::Result<Self> {
+        let reader: Box<dyn Read> = Box::new(reader);
+        let reader = match format {
+            ReplayFormat::Jsonl => RecordReader::Jsonl(BufReader::new(reader)),
+            ReplayFormat::Csv {
+                time_column,
+                time_format,
+            } => {
+                let mut reader = csv::Reader::from_reader(reader);
+                let headers = reader.headers()?.clone();
+                let time_index = headers
+                    .iter()
+                    .position(|header| header == time_column)
+                    .ok_or_else(|| invalid_data(format!("missing time column {time_column}")))?;
+                RecordReader::Csv {
+                    reader,
+                    headers,
+                    time_index,
+                    time_format,
+                }
+            }
+            ReplayFormat::Binary => RecordReader::Binary(BufReader::new(reader)),
+        };
+        Ok(Self {
+            reader,
+            pending: None,
+        })
+    }
+
+    pub fn next_record(&mut self) -> io::Result<Option<T>> {
+        if let Some(record) = self.pending.take() {
+            return Ok(Some(record));
+        }
+        match &mut self.reader {
+            RecordReader::Jsonl(reader) => loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line)? == 0 {
+                    return Ok(None);
+                }
+                if !line.trim().is_empty() {
+                    return Ok(Some(serde_json::from_str(&line)?));
+                }
+            },
+            RecordReader::Csv {
+                reader,
+                headers,
+                time_index,
+                time_format,
+            } => {
+                let mut record = csv::StringRecord::new();
+                if !reader.read_record(&mut record)? {
+                    return Ok(None);
+                }
+                if let Some(time_format) = time_format {
+                    let time = NaiveDateTime::parse_from_str(&record[*time_index], time_format)
+                        .map_err(invalid_data)?;
+                    let time = time.format(ISO_TIME_FORMAT).to_string();
+                    record = record
+                        .iter()
+                        .enumerate()
+                        .map(|(index, field)| match index == *time_index {
+                            true => time.as_str(),
+                            false => field,
+                        })
+                        .collect();
+                }
+                Ok(Some(record.deserialize(Some(headers))?))
+            }
+            RecordReader::Binary(reader) => {
+                let mut len_buf = [0u8; 4];
+                if reader.fill_buf()?.is_empty() {
+                    return Ok(None);
+                }
+                reader.read_exact(&mut len_buf)?;
+                let len = u32::from_le_bytes(len_buf) as usize;
+                if len > MAX_BINARY_RECORD_LEN {
+                    return Err(invalid_data(format!(
+                        "binary record of {len} bytes is too long"
+                    )));
+                }
+                let mut buf = vec![0u8; len];
+                reader.read_exact(&mut buf)?;
+                Ok(Some(bincode::deserialize(&buf).map_err(invalid_data)?))
+            }
+        }
+    }
+}
+
+impl<T: DeserializeOwned + Clone + Sized + GetDataTimeExt> ReplaySource<T> {
+    /// Send the records within the lookahead of the sender's playback clock
+    pub fn feed(&mut self, sender: &TSSender<T>, lookahead: Duration) -> io::Result<usize> {
+        let until = sender.get_clock().position() + lookahead;
+        let mut count = 0;
+        while let Some(record) = self.next_record()? {
+            if record.get_data_time() > until {
+                self.pending = Some(record);
+                break;
+            }
+            sender.send(record);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    pub fn feed_all(&mut self, sender: &TSSender<T>) -> io::Result<usize> {
+        let mut count = 0;
+        while let Some(record) = self.next_record()? {
+            sender.send(record);
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for ReplaySource<T> {
+    type Item = io::Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
@@ -1,6 +1,11 @@
 use crate::utils::time_util::NaiveDateTimeExt;
-use crate::{self as channel};
+use crate::{self as channel, TSRetention};
 use chrono::{Duration, NaiveDateTime};
+use futures_core::Stream;
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::thread::sleep;
 
 #[test]
@@ -94,6 +99,617 @@ fn test_new_time_series_unbounded() {
     assert_eq!(rx2.recv().unwrap().data, 222);
 }
 
+#[test]
+fn test_new_time_series_retention() {
+    let now = NaiveDateTime::now();
+    let (tx, rx) = channel::new_time_series(Some(10), false, now, 1.0);
+    tx.set_retention(Some(TSRetention::MaxSpan(Duration::seconds(30))));
+    tx.send_items(vec![
+        MyTSStruct::new(now - Duration::seconds(40), 111),
+        MyTSStruct::new(now - Duration::seconds(20), 222),
+        MyTSStruct::new(now, 333),
+    ]);
+    assert_eq!(rx.len(), 2);
+    assert_eq!(rx.recv().unwrap().data, 222);
+
+    let (tx, rx) = channel::new_time_series(None, true, now, 1.0);
+    tx.set_retention(Some(TSRetention::MaxAge(Duration::seconds(30))));
+    tx.send_items(vec![
+        MyTSStruct::new(now - Duration::seconds(40), 111),
+        MyTSStruct::new(now - Duration::seconds(20), 222),
+    ]);
+    let rx2 = rx.clone();
+    assert_eq!(rx.recv().unwrap().data, 222);
+    assert_eq!(rx2.recv().unwrap().data, 222);
+
+    let (tx, rx) = channel::new_time_series(None, true, now, 1.0);
+    tx.set_retention(Some(TSRetention::MaxSpan(Duration::seconds(30))));
+    let rx2 = rx.clone();
+    #[cfg(feature = "metrics")]
+    let mut ox = rx.get_observer();
+    tx.send(MyTSStruct::new(now - Duration::seconds(20), 111));
+    assert_eq!(rx.recv().unwrap().data, 111);
+    tx.send(MyTSStruct::new(now + Duration::seconds(20), 222));
+    assert!(rx2.recv().is_none());
+    #[cfg(feature = "metrics")]
+    {
+        let result = ox.get_metrics_result(true);
+        let dropped_count: usize = result.dropped_counts.values().sum();
+        assert_eq!(dropped_count, 1);
+    }
+}
+
+#[test]
+fn test_new_time_series_frame_pacer() {
+    let now = NaiveDateTime::now();
+    let (tx, rx) = channel::new_time_series(None, true, now, 1.0);
+    #[cfg(feature = "metrics")]
+    let mut ox = rx.get_observer();
+    tx.send_items(vec![
+        MyTSStruct::new(now - Duration::milliseconds(30), 111),
+        MyTSStruct::new(now - Duration::milliseconds(20), 222),
+        MyTSStruct::new(now - Duration::milliseconds(10), 333),
+        MyTSStruct::new(now + Duration::milliseconds(1000), 444),
+    ]);
+    for fps in [0.0, -1.0, f64::NAN, f64::INFINITY, 1e-300] {
+        let pacer = channel::TSFramePacer::new(rx.clone(), fps);
+        assert!(matches!(
+            pacer,
+            Err(channel::TSFramePacerError::InvalidFps(_))
+        ));
+    }
+    let mut pacer = channel::TSFramePacer::new(rx, 100.0).unwrap();
+    assert_eq!(pacer.next_frame().unwrap().data, 333);
+    assert_eq!(pacer.next_frame().unwrap().data, 333);
+    assert!(pacer.try_next_frame().is_none());
+    #[cfg(feature = "metrics")]
+    {
+        let result = ox.get_metrics_result(true);
+        let dropped_count: usize = result.dropped_counts.iter().map(|(_, v)| *v).sum();
+        assert_eq!(dropped_count, 2);
+    }
+}
+
+#[test]
+fn test_new_time_series_shared_clock() {
+    let now = NaiveDateTime::now();
+    let clock = channel::PlaybackClock::new(now, 1.0);
+    let (tx1, rx1) = channel::new_time_series_with_clock(None, false, &clock);
+    let (tx2, rx2) = channel::new_time_series_with_clock(None, true, &clock);
+    tx1.send(MyTSStruct::new(now + Duration::seconds(10), 111));
+    tx2.send(MyTSStruct::new(now + Duration::seconds(10), 222));
+    assert!(rx1.recv().is_none());
+    assert!(rx2.recv().is_none());
+    clock.pause();
+    clock.seek(now + Duration::seconds(10));
+    assert!(clock.is_paused());
+    assert_eq!(rx1.get_clock().position(), now + Duration::seconds(10));
+    assert_eq!(rx1.recv().unwrap().data, 111);
+    assert_eq!(rx2.recv().unwrap().data, 222);
+    tx1.send(MyTSStruct::new(now + Duration::seconds(20), 333));
+    tx2.send(MyTSStruct::new(now + Duration::seconds(20), 444));
+    clock.set_speed(1000.0);
+    clock.resume();
+    sleep(std::time::Duration::from_millis(20));
+    assert_eq!(rx1.recv().unwrap().data, 333);
+    assert_eq!(rx2.recv().unwrap().data, 444);
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum MyTSEnum {
+    Audio(i32),
+    Video(i32),
+}
+
+impl channel::GetDataTimeExt for (NaiveDateTime, MyTSEnum) {
+    fn get_data_time(&self) -> NaiveDateTime {
+        self.0
+    }
+}
+
+#[test]
+fn test_new_time_series_merge() {
+    let now = NaiveDateTime::now();
+    let (tx1, rx1) = channel::new_time_series(None, false, now, 1.0);
+    let (tx2, rx2) = channel::new_time_series(None, false, now, 1.0);
+    let mut merge = channel::TSMerge::new();
+    let idle_timeout = std::time::Duration::from_millis(50);
+    merge.add_receiver_map(rx1, idle_timeout, |item: MyTSStruct| {
+        (item.time, MyTSEnum::Audio(item.data))
+    });
+    merge.add_receiver_map(rx2, idle_timeout, |item: MyTSStruct| {
+        (item.time, MyTSEnum::Video(item.data))
+    });
+    tx1.send_items(vec![
+        MyTSStruct::new(now - Duration::milliseconds(30), 1),
+        MyTSStruct::new(now - Duration::milliseconds(10), 3),
+    ]);
+    assert!(merge.try_recv().is_none());
+    tx2.send_items(vec![
+        MyTSStruct::new(now - Duration::milliseconds(20), 2),
+        MyTSStruct::new(now - Duration::milliseconds(5), 4),
+    ]);
+    let items: Vec<_> = merge.recv_items().into_iter().map(|item| item.1).collect();
+    assert_eq!(
+        items,
+        vec![MyTSEnum::Audio(1), MyTSEnum::Video(2), MyTSEnum::Audio(3)]
+    );
+    sleep(idle_timeout);
+    assert_eq!(merge.try_recv().unwrap().1, MyTSEnum::Video(4));
+    assert!(merge.try_recv().is_none());
+}
+
+#[test]
+fn test_new_time_series_window() {
+    let base = NaiveDateTime::default() + Duration::days(20000);
+    let items = vec![
+        MyTSStruct::new(base + Duration::milliseconds(1200), 1),
+        MyTSStruct::new(base + Duration::milliseconds(1700), 2),
+        MyTSStruct::new(base + Duration::milliseconds(2500), 3),
+        MyTSStruct::new(base + Duration::milliseconds(10500), 4),
+    ];
+    let (tx, rx) = channel::new_time_series(None, true, base + Duration::seconds(10), 1.0);
+    let rx2 = rx.clone();
+    tx.send_items(items);
+    let mut tumbling = channel::TSWindow::tumbling(rx, Duration::seconds(1));
+    let windows = tumbling.recv_items();
+    assert_eq!(windows.len(), 2);
+    assert_eq!(windows[0].start, base + Duration::seconds(1));
+    assert_eq!(windows[0].end, base + Duration::seconds(2));
+    let datas: Vec<_> = windows[0].items.iter().map(|item| item.data).collect();
+    assert_eq!(datas, vec![1, 2]);
+    assert_eq!(windows[1].items.len(), 1);
+
+    let mut sliding = channel::TSWindow::sliding_fold(
+        rx2,
+        Duration::seconds(2),
+        Duration::seconds(1),
+        || 0,
+        |sum, item: MyTSStruct| *sum += item.data,
+    );
+    sliding.set_allowed_lateness(Duration::milliseconds(500));
+    let sums: Vec<_> = sliding.recv_items().into_iter().map(|w| w.items).collect();
+    assert_eq!(sums, vec![3, 6, 3]);
+
+    // beyond the range of i64 nanoseconds since the epoch
+    let base = chrono::NaiveDate::from_ymd_opt(2500, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    let (tx, rx) = channel::new_time_series(None, false, base + Duration::seconds(10), 1.0);
+    tx.send(MyTSStruct::new(base + Duration::milliseconds(1200), 1));
+    let windows = channel::TSWindow::tumbling(rx, Duration::seconds(1)).recv_items();
+    assert_eq!(windows.len(), 1);
+    assert_eq!(windows[0].start, base + Duration::seconds(1));
+}
+
+impl channel::Interpolate for MyTSStruct {
+    fn interpolate(&self, next: &Self, ratio: f64) -> Self {
+        let span = (next.time - self.time).num_milliseconds() as f64;
+        Self {
+            time: self.time + Duration::milliseconds((span * ratio).round() as i64),
+            data: self.data + ((next.data - self.data) as f64 * ratio).round() as i32,
+        }
+    }
+}
+
+#[test]
+fn test_new_time_series_resample() {
+    let base = NaiveDateTime::default() + Duration::days(20000);
+    let clock = channel::PlaybackClock::new(base, 1.0);
+    clock.pause();
+    clock.seek(base + Duration::milliseconds(500));
+    let (tx, rx) = channel::new_time_series_with_clock(None, true, &clock);
+    let (rx2, rx3, rx4) = (rx.clone(), rx.clone(), rx.clone());
+    tx.send_items(vec![
+        MyTSStruct::new(base + Duration::milliseconds(50), 0),
+        MyTSStruct::new(base + Duration::milliseconds(150), 100),
+        MyTSStruct::new(base + Duration::milliseconds(420), 370),
+    ]);
+    let interval = Duration::milliseconds(100);
+    let samples = channel::TSResampler::last_value_hold(rx, interval).recv_items();
+    assert_eq!(samples[0].time, base + interval);
+    let datas: Vec<_> = samples.iter().map(|sample| sample.value.data).collect();
+    assert_eq!(datas, vec![0, 100, 100, 100, 370]);
+
+    let samples = channel::TSResampler::nearest(rx2, interval).recv_items();
+    let datas: Vec<_> = samples.iter().map(|sample| sample.value.data).collect();
+    assert_eq!(datas, vec![0, 100, 370, 370]);
+
+    let samples = channel::TSResampler::linear(rx3, interval).recv_items();
+    let datas: Vec<_> = samples.iter().map(|sample| sample.value.data).collect();
+    assert_eq!(datas, vec![50, 150, 250, 350]);
+    assert_eq!(samples[1].value.time, samples[1].time);
+
+    let mut sums = channel::TSResampler::aggregate(
+        rx4,
+        Duration::milliseconds(200),
+        || 0,
+        |sum, item: MyTSStruct| *sum += item.data,
+    );
+    let datas: Vec<_> = sums.recv_items().into_iter().map(|s| s.value).collect();
+    assert_eq!(datas, vec![100, 0]);
+    clock.seek(base + Duration::milliseconds(600));
+    assert_eq!(sums.try_recv().unwrap().value, 370);
+}
+
+#[test]
+#[should_panic(expected = "interval must be positive")]
+fn test_new_time_series_resample_zero_interval() {
+    let (_tx, rx) = channel::new_time_series::<MyTSStruct>(None, false, NaiveDateTime::now(), 1.0);
+    channel::TSResampler::last_value_hold(rx, Duration::zero());
+}
+
+#[cfg(feature = "replay")]
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct MyReplayStruct {
+    time: NaiveDateTime,
+    data: i32,
+}
+
+#[cfg(feature = "replay")]
+impl channel::GetDataTimeExt for MyReplayStruct {
+    fn get_data_time(&self) -> NaiveDateTime {
+        self.time
+    }
+}
+
+#[cfg(feature = "replay")]
+#[test]
+fn test_replay_round_trip() {
+    use channel::replay::{Recorder, ReplayFormat, ReplaySource};
+    let now = NaiveDateTime::now();
+    let formats = vec![
+        ("jsonl", ReplayFormat::Jsonl),
+        ("csv", ReplayFormat::csv("time")),
+        ("bin", ReplayFormat::Binary),
+    ];
+    for (ext, format) in formats {
+        let path = std::env::temp_dir().join(format!("channel_test_replay.{ext}"));
+        let (tx, rx) = channel::new_time_series(None, false, now, 1.0);
+        tx.send_items(vec![
+            MyReplayStruct {
+                time: now - Duration::milliseconds(20),
+                data: 111,
+            },
+            MyReplayStruct {
+                time: now - Duration::milliseconds(10),
+                data: 222,
+            },
+            MyReplayStruct {
+                time: now + Duration::seconds(10),
+                data: 333,
+            },
+        ]);
+        {
+            let mut recorder = Recorder::create(&path, format.clone()).unwrap();
+            assert_eq!(recorder.tee_ts(&rx).unwrap().len(), 2);
+            recorder
+                .record(&rx.get_observer().query_items(0, None)[0])
+                .unwrap();
+        }
+        let mut source = ReplaySource::<MyReplayStruct>::open(&path, format).unwrap();
+        let (tx2, rx2) = channel::new_time_series(None, false, now, 1.0);
+        assert_eq!(source.feed(&tx2, Duration::zero()).unwrap(), 2);
+        assert_eq!(rx2.recv().unwrap().data, 111);
+        assert_eq!(rx2.recv().unwrap().data, 222);
+        let last = source.next().unwrap().unwrap();
+        assert_eq!(last.time, now + Duration::seconds(10));
+        assert!(source.next().is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+#[cfg(feature = "replay")]
+#[test]
+fn test_replay_binary_record_too_long() {
+    use channel::replay::{ReplayFormat, ReplaySource};
+    let data = u32::MAX.to_le_bytes().to_vec();
+    let mut source = ReplaySource::<MyReplayStruct>::from_reader(
+        std::io::Cursor::new(data),
+        ReplayFormat::Binary,
+    )
+    .unwrap();
+    let err = source.next_record().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[cfg(feature = "replay")]
+#[test]
+fn test_replay_csv_time_format() {
+    use channel::replay::{ReplayFormat, ReplaySource};
+    let data = "data,time\n1,2024-01-02 03:04:05\n2,2024-01-02 03:04:06\n";
+    let format = ReplayFormat::Csv {
+        time_column: "time".to_string(),
+        time_format: Some("%Y-%m-%d %H:%M:%S".to_string()),
+    };
+    let source = ReplaySource::<MyReplayStruct>::from_reader(data.as_bytes(), format).unwrap();
+    let records: Vec<_> = source.map(|record| record.unwrap()).collect();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[1].data, 2);
+    assert_eq!(
+        records[1].time,
+        NaiveDateTime::parse_from_str("2024-01-02 03:04:06", "%Y-%m-%d %H:%M:%S").unwrap()
+    );
+}
+
+#[test]
+fn test_new_time_series_loop_and_reverse() {
+    let base = NaiveDateTime::now();
+    let clock = channel::PlaybackClock::new(base, 1.0);
+    let (tx, rx) = channel::new_time_series_with_clock(None, false, &clock);
+    tx.set_loop(true);
+    tx.send_items(vec![
+        MyTSStruct::new(base, 111),
+        MyTSStruct::new(base + Duration::milliseconds(20), 222),
+    ]);
+    assert_eq!(rx.recv().unwrap().data, 111);
+    assert!(rx.recv().is_none());
+    sleep(std::time::Duration::from_millis(25));
+    assert_eq!(rx.recv().unwrap().data, 222);
+    // a live stream that caught up with its sender does not loop
+    assert!(rx.recv().is_none());
+    assert!(rx.is_empty());
+    tx.finish();
+    assert!(!rx.is_finished());
+    assert_eq!(rx.recv().unwrap().data, 111);
+    assert!(rx.recv().is_none());
+    assert_eq!(rx.len(), 1);
+    assert!(rx.playback_position() < base + Duration::milliseconds(20));
+    // the shared clock is not rewound
+    assert!(clock.position() >= base + Duration::milliseconds(25));
+
+    let (tx, rx) = channel::new_time_series(Some(10), true, base, -1.0);
+    tx.send_items(vec![
+        MyTSStruct::new(base - Duration::milliseconds(20), 111),
+        MyTSStruct::new(base - Duration::milliseconds(10), 222),
+        MyTSStruct::new(base, 333),
+    ]);
+    let rx2 = rx.clone();
+    assert_eq!(rx.recv().unwrap().data, 333);
+    assert!(rx.recv().is_none());
+    sleep(std::time::Duration::from_millis(25));
+    let datas: Vec<_> = rx
+        .recv_items_weak(10)
+        .iter()
+        .map(|item| item.data)
+        .collect();
+    assert_eq!(datas, vec![222, 111]);
+    let datas: Vec<_> = rx2.recv_items(3).iter().map(|item| item.data).collect();
+    assert_eq!(datas, vec![333, 222, 111]);
+
+    // a reverse loop restarts at the end of the data span
+    let (tx, rx) = channel::new_time_series(None, true, base - Duration::seconds(10), -1.0);
+    tx.set_loop(true);
+    tx.send_items(vec![
+        MyTSStruct::new(base, 111),
+        MyTSStruct::new(base + Duration::seconds(1), 222),
+    ]);
+    tx.finish();
+    let datas: Vec<_> = rx.recv_items(2).iter().map(|item| item.data).collect();
+    assert_eq!(datas, vec![222, 111]);
+    assert_eq!(rx.recv().unwrap().data, 222);
+    assert!(rx.recv().is_none());
+    assert!(rx.playback_position() <= base + Duration::seconds(1));
+}
+
+#[test]
+fn test_new_time_series_bound_and_retention() {
+    let base = NaiveDateTime::now();
+    // the loop history keeps at most the bound
+    let (tx, rx) = channel::new_time_series(Some(2), false, base, 1.0);
+    tx.set_loop(true);
+    for data in [111, 222, 333] {
+        tx.send(MyTSStruct::new(
+            base - Duration::seconds(3 - data as i64 / 111),
+            data,
+        ));
+        assert_eq!(rx.recv().unwrap().data, data);
+    }
+    tx.finish();
+    assert_eq!(rx.recv().unwrap().data, 222);
+    assert_eq!(rx.len(), 1);
+
+    // when playing backwards the items released first are evicted
+    let (tx, rx) = channel::new_time_series(Some(2), false, base, -1.0);
+    tx.send_items(vec![
+        MyTSStruct::new(base + Duration::seconds(1), 111),
+        MyTSStruct::new(base + Duration::seconds(2), 222),
+        MyTSStruct::new(base + Duration::seconds(3), 333),
+    ]);
+    let datas: Vec<_> = rx.recv_items(2).iter().map(|item| item.data).collect();
+    assert_eq!(datas, vec![222, 111]);
+
+    let (tx, rx) = channel::new_time_series(None, false, base, -1.0);
+    tx.set_retention(Some(TSRetention::MaxAge(Duration::seconds(2))));
+    tx.send_items(vec![
+        MyTSStruct::new(base - Duration::seconds(2), 111),
+        MyTSStruct::new(base + Duration::seconds(1), 222),
+        MyTSStruct::new(base + Duration::seconds(3), 333),
+    ]);
+    assert_eq!(rx.recv().unwrap().data, 222);
+    assert!(rx.recv().is_none());
+    assert_eq!(rx.len(), 1);
+}
+
+#[test]
+fn test_new_time_series_receiver_view() {
+    let now = NaiveDateTime::now();
+    let (tx, rx) = channel::new_time_series(None, true, now, 1.0);
+    let delayed_rx = rx.clone();
+    delayed_rx.set_view(Duration::milliseconds(20), 1.0);
+    let fast_rx = rx.clone();
+    fast_rx.set_view(Duration::zero(), 1000.0);
+    tx.send_items(vec![
+        MyTSStruct::new(now - Duration::milliseconds(5), 111),
+        MyTSStruct::new(now + Duration::seconds(1), 222),
+    ]);
+    sleep(std::time::Duration::from_millis(5));
+    assert_eq!(rx.recv().unwrap().data, 111);
+    assert!(rx.recv().is_none());
+    assert!(delayed_rx.recv().is_none());
+    assert!(delayed_rx.playback_position() < rx.playback_position());
+    assert_eq!(fast_rx.recv_items(2).len(), 2);
+    assert_eq!(delayed_rx.len(), 2);
+    sleep(std::time::Duration::from_millis(25));
+    assert_eq!(delayed_rx.recv().unwrap().data, 111);
+    assert!(delayed_rx.recv().is_none());
+
+    // a seek moves the view with the clock, only the time played since is scaled
+    let clock = rx.get_clock();
+    clock.pause();
+    clock.seek(now + Duration::days(1));
+    assert_eq!(fast_rx.playback_position(), now + Duration::days(1));
+    assert_eq!(
+        delayed_rx.playback_position(),
+        now + Duration::days(1) - Duration::milliseconds(20)
+    );
+}
+
+#[test]
+#[should_panic(expected = "dispatch")]
+fn test_new_time_series_receiver_view_without_dispatch() {
+    let (_tx, rx) = channel::new_time_series::<MyTSStruct>(None, false, NaiveDateTime::now(), 1.0);
+    rx.set_view(Duration::seconds(1), 1.0);
+}
+
+#[test]
+fn test_new_time_series_gap_detection() {
+    let now = NaiveDateTime::now();
+    let (tx, rx) = channel::new_time_series(None, false, now, 1.0);
+    #[cfg(feature = "metrics")]
+    let mut ox = rx.get_observer();
+    rx.set_gap_detection(Some(Duration::milliseconds(10)));
+    tx.send(MyTSStruct::new(now, 111));
+    assert!(matches!(rx.recv_event(), Some(channel::TSEvent::Item(item)) if item.data == 111));
+    assert!(rx.recv_event().is_none());
+    let last_data_time = rx.playback_position();
+    sleep(std::time::Duration::from_millis(25));
+    match rx.recv_event() {
+        Some(channel::TSEvent::Gap { from, to }) => {
+            assert!(from <= last_data_time);
+            assert!(to - from > Duration::milliseconds(10));
+        }
+        event => panic!("unexpected event {event:?}"),
+    }
+    assert!(rx.recv_event().is_none());
+    tx.send(MyTSStruct::new(rx.playback_position(), 222));
+    assert_eq!(rx.recv().unwrap().data, 222);
+    #[cfg(feature = "metrics")]
+    {
+        let result = ox.get_metrics_result(true);
+        let gap_count: usize = result.gap_counts.iter().map(|(_, v)| *v).sum();
+        assert_eq!(gap_count, 1);
+    }
+
+    let clock = channel::PlaybackClock::new(now, 1.0);
+    clock.pause();
+    clock.seek(now);
+    let (_tx, rx) = channel::new_time_series_with_clock(None, false, &clock);
+    rx.set_heartbeat(Duration::milliseconds(10), |data_time| {
+        MyTSStruct::new(data_time, 0)
+    });
+    clock.seek(now + Duration::milliseconds(25));
+    assert_eq!(rx.recv().unwrap().time, now + Duration::milliseconds(10));
+    assert_eq!(rx.recv().unwrap().time, now + Duration::milliseconds(20));
+    assert!(rx.recv().is_none());
+
+    // the item after a gap stays in the buffer for the other receive paths
+    let (tx, rx) = channel::new_time_series_with_clock(None, false, &clock);
+    rx.set_gap_detection(Some(Duration::milliseconds(10)));
+    tx.send(MyTSStruct::new(now + Duration::milliseconds(50), 333));
+    clock.seek(now + Duration::milliseconds(50));
+    assert!(matches!(
+        rx.recv_event(),
+        Some(channel::TSEvent::Gap { .. })
+    ));
+    assert_eq!(rx.len(), 1);
+    let datas: Vec<_> = rx.recv_items(1).iter().map(|item| item.data).collect();
+    assert_eq!(datas, vec![333]);
+    assert!(rx.recv_event().is_none());
+
+    // playing backwards the gaps open towards older data times
+    let clock = channel::PlaybackClock::new(now, -1.0);
+    clock.pause();
+    clock.seek(now);
+    let (tx, rx) = channel::new_time_series_with_clock(None, false, &clock);
+    rx.set_heartbeat(Duration::milliseconds(10), |data_time| {
+        MyTSStruct::new(data_time, 0)
+    });
+    tx.send(MyTSStruct::new(now - Duration::milliseconds(25), 444));
+    clock.seek(now - Duration::milliseconds(25));
+    assert_eq!(rx.recv().unwrap().time, now - Duration::milliseconds(10));
+    assert_eq!(rx.recv().unwrap().time, now - Duration::milliseconds(20));
+    assert_eq!(rx.recv().unwrap().data, 444);
+    assert!(rx.recv().is_none());
+}
+
+#[test]
+fn test_new_time_series_finish() {
+    let now = NaiveDateTime::now();
+    let clock = channel::PlaybackClock::new(now, 1.0);
+    clock.pause();
+    clock.seek(now);
+    let (tx, rx) = channel::new_time_series_with_clock(None, true, &clock);
+    let ox = rx.get_observer();
+    tx.send_items(vec![
+        MyTSStruct::new(now + Duration::milliseconds(10), 111),
+        MyTSStruct::new(now + Duration::milliseconds(30), 222),
+    ]);
+    assert!(rx.next_due_in().is_none());
+    assert_eq!(
+        rx.data_span(),
+        Some((
+            now + Duration::milliseconds(10),
+            now + Duration::milliseconds(30)
+        ))
+    );
+    assert_eq!(ox.playback_position(), now);
+    clock.resume();
+    let due_in = ox.next_due_in().unwrap();
+    assert!(due_in > std::time::Duration::from_millis(5));
+    assert!(due_in <= std::time::Duration::from_millis(10));
+    tx.finish();
+    assert!(!rx.is_finished());
+    assert_eq!(rx.recv_wait().unwrap().data, 111);
+    assert!(clock.position() >= now + Duration::milliseconds(10));
+    assert_eq!(rx.recv_wait().unwrap().data, 222);
+    assert!(rx.recv_wait().is_none());
+    assert!(rx.is_finished());
+    assert!(ox.is_finished());
+    assert!(matches!(rx.recv_event(), Some(channel::TSEvent::Finished)));
+}
+
+#[test]
+fn test_new_time_series_recv_wait_wakes() {
+    let now = NaiveDateTime::now();
+    let clock = channel::PlaybackClock::new(now, 1.0);
+    clock.pause();
+    let (tx, rx) = channel::new_time_series_with_clock::<MyTSStruct>(None, false, &clock);
+    let handle = std::thread::spawn(move || {
+        let data = rx.recv_wait().map(|item| item.data);
+        (data, rx.recv_wait().is_none())
+    });
+    sleep(std::time::Duration::from_millis(20));
+    tx.send(MyTSStruct::new(now, 111));
+    tx.finish();
+    assert_eq!(handle.join().unwrap(), (Some(111), true));
+}
+
+#[tokio::test]
+async fn test_new_time_series_recv_wait_async_wakes() {
+    let now = NaiveDateTime::now();
+    let clock = channel::PlaybackClock::new(now, 1.0);
+    clock.pause();
+    let (tx, rx) = channel::new_time_series_with_clock(None, false, &clock);
+    tx.send(MyTSStruct::new(now + Duration::milliseconds(5), 111));
+    let task = tokio::spawn(async move { rx.recv_wait_async().await.map(|item| item.data) });
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    assert!(!task.is_finished());
+    clock.resume();
+    assert_eq!(task.await.unwrap(), Some(111));
+}
+
 #[test]
 fn test_new_unbounded_weak() {
     let (tx, rx) = channel::new(None, true);
@@ -127,6 +743,39 @@ fn test_new_time_series_unbounded_weak() {
     }
 }
 
+#[test]
+fn test_new_dispatch_recv_items_after_recv() {
+    for bounded in [None, Some(8)] {
+        let (tx, rx) = channel::new(bounded, true);
+        let rx2 = rx.clone();
+        tx.send_items(vec![1, 2, 3, 4]);
+        assert_eq!(rx.recv().unwrap(), 1);
+        assert_eq!(rx.recv_items(2), vec![2, 3]);
+        assert_eq!(rx2.recv_items(4), vec![1, 2, 3, 4]);
+        assert_eq!(rx.recv_items_weak(2), vec![4]);
+    }
+}
+
+#[test]
+fn test_new_time_series_recv_items_partly_due() {
+    let now = NaiveDateTime::now();
+    for bounded in [None, Some(8)] {
+        let (tx, rx) = channel::new_time_series(bounded, false, now, 1.0);
+        tx.send_items(vec![
+            MyTSStruct::new(now - Duration::seconds(2), 111),
+            MyTSStruct::new(now - Duration::seconds(1), 222),
+            MyTSStruct::new(now + Duration::seconds(10), 333),
+        ]);
+        let items = rx.recv_items_weak(3);
+        assert_eq!(
+            items.iter().map(|item| item.data).collect::<Vec<_>>(),
+            vec![111, 222]
+        );
+        assert!(rx.recv_items(1).is_empty());
+        assert_eq!(rx.len(), 1);
+    }
+}
+
 #[test]
 fn test_new_unbounded_bidirectional() {
     let (mut reqx, mut respx) = channel::new_unbounded_bidirectional();
@@ -140,28 +789,716 @@ fn test_new_unbounded_bidirectional() {
         let xdata = respx.try_take_request().unwrap();
         respx.reply_response(xdata + 1);
     }
-    assert_eq!(reqx.try_get_response().unwrap(), 13);
-    assert_eq!(reqy.try_get_response().unwrap(), 16);
-}
-
-// #[tokio::test]
-// async fn test_new_unbounded_bidirectional_async() {
-//     let (mut reqx, mut respx) = channel::new_unbounded_bidirectional_async();
-//     _ = tokio::join! {
-//         tokio::spawn(async move {
-//             let ret: usize = reqx.request(12).await;
-//             assert_eq!(ret, 13);
-//         }),
-//         tokio::spawn(async move {
-//             loop {
-//                 match respx.take_request() {
-//                     Some((data, sender)) => {
-//                         _ = sender.send(data);
-//                         break;
-//                     },
-//                     None => (),
-//                 }
-//             }
-//         })
-//     };
-// }
+    assert_eq!(reqx.try_get_response().unwrap(), Ok(13));
+    assert_eq!(reqy.try_get_response().unwrap(), Ok(16));
+}
+
+#[test]
+fn test_new_unbounded_bidirectional_reply_handle() {
+    let (mut reqx, mut respx) = channel::new_unbounded_bidirectional();
+    reqx.send_requests(vec![12, 15, 18]);
+    let (xdata, xhandle) = respx.take_request().unwrap();
+    let mut respy = respx.clone();
+    let (ydata, yhandle) = respy.take_request().unwrap();
+    let (_, zhandle) = respy.take_request().unwrap();
+    std::thread::spawn(move || yhandle.reply(ydata + 1))
+        .join()
+        .unwrap();
+    assert!(reqx.try_get_response().is_none());
+    xhandle.reply(xdata + 1);
+    drop(zhandle);
+    assert_eq!(reqx.try_get_response().unwrap(), Ok(13));
+    assert_eq!(reqx.try_get_response().unwrap(), Ok(16));
+    assert_eq!(
+        reqx.try_get_response().unwrap(),
+        Err(channel::BDError::NoResponse)
+    );
+}
+
+#[test]
+fn test_new_unbounded_bidirectional_ticket() {
+    let (mut reqx, mut respx) = channel::new_unbounded_bidirectional();
+    let tickets = reqx.send_requests(vec![12, 15, 18]);
+    let (_, xhandle) = respx.take_request().unwrap();
+    let (ydata, yhandle) = respx.take_request().unwrap();
+    let (zdata, zhandle) = respx.take_request().unwrap();
+    zhandle.reply(zdata + 1);
+    assert!(reqx.try_get_response().is_none());
+    assert!(reqx.try_get_response_for(tickets[1]).is_none());
+    assert_eq!(reqx.try_get_any_response(), Some((tickets[2], Ok(19))));
+    assert!(reqx.try_get_response_for(tickets[2]).is_none());
+    let timeout = std::time::Duration::from_millis(10);
+    assert!(reqx.get_any_response(timeout).is_none());
+    let replier = std::thread::spawn(move || {
+        sleep(std::time::Duration::from_millis(5));
+        yhandle.reply(ydata + 1);
+    });
+    let timeout = std::time::Duration::from_secs(5);
+    assert_eq!(reqx.get_response_for(tickets[1], timeout), Some(Ok(16)));
+    replier.join().unwrap();
+    drop(xhandle);
+    assert_eq!(
+        reqx.get_any_response(timeout),
+        Some((tickets[0], Err(channel::BDError::NoResponse)))
+    );
+    assert!(reqx.get_any_response(timeout).is_none());
+}
+
+#[test]
+fn test_new_unbounded_bidirectional_stream() {
+    let (mut reqx, mut respx) = channel::new_unbounded_bidirectional();
+    let stream = reqx.request_stream(3).unwrap();
+    let (count, mut handle) = respx.take_request().unwrap();
+    std::thread::spawn(move || {
+        for page in 0..count {
+            handle.send(page * 10);
+        }
+        handle.finish();
+    });
+    assert_eq!(stream.collect::<Vec<_>>(), vec![0, 10, 20]);
+
+    // without a responder the requests fail at once instead of queuing
+    drop(respx);
+    let ticket = reqx.send_request(4);
+    let timeout = std::time::Duration::from_secs(5);
+    assert_eq!(
+        reqx.get_response_for(ticket, timeout),
+        Some(Err(channel::BDError::Disconnected))
+    );
+    assert!(matches!(
+        reqx.request_stream(5),
+        Err(channel::BDError::Disconnected)
+    ));
+    assert_eq!(reqx.queue_len(), 0);
+}
+
+#[test]
+fn test_new_bounded_bidirectional() {
+    let (mut reqx, mut respx) = channel::new_bounded_bidirectional(2);
+    reqx.send_requests(vec![12, 15]);
+    assert_eq!(reqx.try_send_request(18), Err(channel::BDError::Full));
+    let timeout = std::time::Duration::from_millis(10);
+    assert_eq!(
+        reqx.send_request_timeout(18, timeout),
+        Err(channel::BDError::Timeout)
+    );
+    let (xdata, xhandle) = respx.take_request().unwrap();
+    assert_eq!((reqx.queue_len(), reqx.in_flight()), (1, 1));
+    assert_eq!(reqx.try_send_request(18), Err(channel::BDError::Full));
+    let replier = std::thread::spawn(move || {
+        sleep(std::time::Duration::from_millis(5));
+        xhandle.reply(xdata + 1);
+    });
+    let ticket = reqx.send_request(18);
+    replier.join().unwrap();
+    assert_eq!((respx.queue_len(), respx.in_flight()), (2, 0));
+    assert_eq!(reqx.try_get_response().unwrap(), Ok(13));
+    drop(respx.take_request());
+    let (zdata, zhandle) = respx.take_request().unwrap();
+    zhandle.reply(zdata + 1);
+    assert_eq!(reqx.try_get_response_for(ticket), Some(Ok(19)));
+    assert_eq!((reqx.queue_len(), reqx.in_flight()), (0, 0));
+}
+
+#[test]
+fn test_new_unbounded_bidirectional_request_all() {
+    let (mut reqx, mut respx) = channel::new_unbounded_bidirectional::<usize, usize>();
+    let mut respy = respx.clone();
+    let respz = respx.clone();
+    let pending = reqx.send_request_all(3).unwrap();
+    assert_eq!(pending.responders(), &[respx.id(), respy.id(), respz.id()]);
+    let (xdata, xhandle) = respx.take_request().unwrap();
+    let (ydata, yhandle) = respy.take_request().unwrap();
+    assert_eq!((reqx.queue_len(), reqx.in_flight()), (1, 2));
+    let replier = std::thread::spawn(move || {
+        xhandle.reply(xdata + 1);
+        yhandle.reply(ydata + 2);
+    });
+    let timeout = std::time::Duration::from_millis(50);
+    let gather = pending.collect(channel::BDQuorum::All, timeout);
+    replier.join().unwrap();
+    let mut responses = gather.responses.clone();
+    responses.sort();
+    assert_eq!(responses, vec![(respx.id(), 4), (respy.id(), 5)]);
+    assert_eq!(gather.missing, vec![respz.id()]);
+    drop(respz);
+    assert_eq!(reqx.queue_len(), 0);
+    let pending = reqx.send_request_all(5).unwrap();
+    let (ydata, yhandle) = respy.take_request().unwrap();
+    yhandle.reply(ydata + 2);
+    let gather = pending.collect(channel::BDQuorum::First(1), timeout);
+    assert_eq!(gather.responses, vec![(respy.id(), 7)]);
+    assert_eq!(gather.missing, vec![respx.id()]);
+    drop(respx.take_request());
+    let gather = reqx
+        .request_all(7, channel::BDQuorum::All, timeout)
+        .unwrap();
+    assert_eq!(gather.responses, vec![]);
+    assert_eq!(gather.missing, vec![respx.id(), respy.id()]);
+}
+
+#[test]
+fn test_new_unbounded_bidirectional_priority() {
+    let (mut reqx, mut respx) = channel::new_unbounded_bidirectional();
+    let now = std::time::Instant::now();
+    let xticket = reqx.send_request(12);
+    let yticket = reqx.send_request_with(15, 5, None);
+    let zticket = reqx.send_request_with(18, 9, Some(now));
+    let later = now + std::time::Duration::from_secs(60);
+    reqx.send_request_with(21, 5, Some(later));
+    let mut taken = vec![];
+    while let Some((data, handle)) = respx.take_request() {
+        taken.push(data);
+        handle.reply(data + 1);
+    }
+    assert_eq!(taken, vec![15, 21, 12]);
+    assert_eq!(
+        reqx.try_get_response_for(zticket),
+        Some(Err(channel::BDError::DeadlineExceeded))
+    );
+    assert_eq!(reqx.try_get_response_for(yticket), Some(Ok(16)));
+    assert_eq!(reqx.try_get_response_for(xticket), Some(Ok(13)));
+}
+
+#[tokio::test]
+async fn test_new_unbounded_bidirectional_async() {
+    let (mut reqx, mut respx) = channel::new_unbounded_bidirectional_async();
+    let (req_ret, resp_ret) = tokio::join! {
+        tokio::spawn(async move {
+            let ret: usize = reqx.request(12).await.unwrap();
+            assert_eq!(ret, 13);
+        }),
+        tokio::spawn(async move {
+            let (data, handle) = respx.take_request().await.unwrap();
+            handle.reply(data + 1);
+        })
+    };
+    req_ret.unwrap();
+    resp_ret.unwrap();
+}
+
+#[tokio::test]
+async fn test_new_unbounded_bidirectional_async_timeout() {
+    let (mut reqx, mut respx) = channel::new_unbounded_bidirectional_async::<usize, usize>();
+    let timeout = std::time::Duration::from_millis(10);
+    assert_eq!(
+        reqx.request_timeout(12, timeout).await,
+        Err(channel::BDError::Timeout)
+    );
+    assert!(respx.try_take_request().await.is_none());
+
+    let mut reqy = reqx.clone();
+    let request = tokio::spawn(async move { reqy.request(15).await });
+    let (data, mut handle) = respx.take_request().await.unwrap();
+    assert_eq!(data, 15);
+    assert!(!handle.is_cancelled());
+    request.abort();
+    handle.cancelled().await;
+    assert!(handle.is_cancelled());
+
+    let request = tokio::spawn(async move { reqx.request(18).await });
+    let (_, handle) = respx.take_request().await.unwrap();
+    drop(handle);
+    assert_eq!(request.await.unwrap(), Err(channel::BDError::NoResponse));
+}
+
+#[tokio::test]
+async fn test_new_unbounded_bidirectional_async_disconnected() {
+    let (mut reqx, respx) = channel::new_unbounded_bidirectional_async::<usize, usize>();
+    let mut reqy = reqx.clone();
+    let request = tokio::spawn(async move { reqy.request(12).await });
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    drop(respx);
+    assert_eq!(request.await.unwrap(), Err(channel::BDError::Disconnected));
+    assert_eq!(reqx.request(15).await, Err(channel::BDError::Disconnected));
+}
+
+#[tokio::test]
+async fn test_new_bounded_bidirectional_async() {
+    let (mut reqx, mut respx) = channel::new_bounded_bidirectional_async::<usize, usize>(1);
+    let mut reqy = reqx.clone();
+    let request = tokio::spawn(async move { reqy.request(12).await });
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    assert_eq!(reqx.try_request(15).await, Err(channel::BDError::Full));
+    let (data, handle) = respx.take_request().await.unwrap();
+    assert_eq!((reqx.queue_len(), reqx.in_flight()), (0, 1));
+    let timeout = std::time::Duration::from_millis(10);
+    assert_eq!(
+        reqx.request_timeout(15, timeout).await,
+        Err(channel::BDError::Timeout)
+    );
+    handle.reply(data + 1);
+    assert_eq!(request.await.unwrap(), Ok(13));
+    let responder = tokio::spawn(async move {
+        let (data, handle) = respx.take_request().await.unwrap();
+        handle.reply(data + 1);
+    });
+    assert_eq!(reqx.request(15).await, Ok(16));
+    responder.await.unwrap();
+    assert_eq!((reqx.queue_len(), reqx.in_flight()), (0, 0));
+}
+
+#[tokio::test]
+async fn test_new_unbounded_bidirectional_async_request_all() {
+    let (mut reqx, respx) = channel::new_unbounded_bidirectional_async::<usize, usize>();
+    let mut ids = vec![];
+    for (mut resp, offset) in [(respx.clone(), 1), (respx.clone(), 2)] {
+        ids.push(resp.id());
+        tokio::spawn(async move {
+            loop {
+                let (data, handle) = resp.take_request().await.unwrap();
+                handle.reply(data + offset);
+            }
+        });
+    }
+    let timeout = std::time::Duration::from_millis(50);
+    let gather = reqx
+        .request_all(3, channel::BDQuorum::All, timeout)
+        .await
+        .unwrap();
+    let mut responses = gather.responses.clone();
+    responses.sort();
+    assert_eq!(responses, vec![(ids[0], 4), (ids[1], 5)]);
+    assert_eq!(gather.missing, vec![respx.id()]);
+    let timeout = std::time::Duration::from_secs(5);
+    let gather = reqx
+        .request_all(3, channel::BDQuorum::First(1), timeout)
+        .await
+        .unwrap();
+    assert_eq!(gather.responses.len(), 1);
+    assert_eq!(gather.missing.len(), 2);
+    drop(respx);
+    let response = reqx.request(3).await.unwrap();
+    assert!(response == 4 || response == 5);
+}
+
+#[tokio::test]
+async fn test_serve() {
+    let (reqx, respx) = channel::new_unbounded_bidirectional_async::<usize, usize>();
+    let running = Arc::new(AtomicUsize::new(0));
+    let max_running = Arc::new(AtomicUsize::new(0));
+    let (running2, max_running2) = (running.clone(), max_running.clone());
+    let server = channel::serve(respx, 2, move |data: usize| {
+        let (running, max_running) = (running2.clone(), max_running2.clone());
+        async move {
+            let count = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running.fetch_max(count, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+            if data == 0 {
+                panic!("bad request");
+            }
+            data + 1
+        }
+    });
+    let mut requests = vec![];
+    for data in 0..6 {
+        let mut reqx = reqx.clone();
+        requests.push(tokio::spawn(async move { reqx.request(data).await }));
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+    #[cfg(feature = "metrics")]
+    let result = server.get_metrics_result(false);
+    server.shutdown().await;
+    let mut responses = vec![];
+    for request in requests {
+        responses.push(request.await.unwrap());
+    }
+    assert_eq!(max_running.load(Ordering::SeqCst), 2);
+    assert_eq!(responses[0], Err(channel::BDError::Panicked));
+    assert_eq!(responses[1..4], [Ok(2), Ok(3), Ok(4)]);
+    assert_eq!(responses[4], Err(channel::BDError::Disconnected));
+    #[cfg(feature = "metrics")]
+    {
+        assert_eq!((result.handled, result.panicked), (2, 1));
+        assert!(result.mean_latency().unwrap() >= std::time::Duration::from_millis(100));
+    }
+}
+
+#[tokio::test]
+async fn test_serve_handler_panics_before_future() {
+    let (mut reqx, respx) = channel::new_unbounded_bidirectional_async::<usize, usize>();
+    // a zero concurrency is served one request at a time
+    let server = channel::serve(respx, 0, |data: usize| {
+        if data == 0 {
+            panic!("bad request");
+        }
+        async move { data + 1 }
+    });
+    assert_eq!(reqx.request(0).await, Err(channel::BDError::Panicked));
+    assert_eq!(reqx.request(1).await, Ok(2));
+    assert!(!server.is_finished());
+    server.shutdown().await;
+}
+
+#[cfg(feature = "tower")]
+struct AddOne;
+
+#[cfg(feature = "tower")]
+impl tower_service::Service<usize> for AddOne {
+    type Response = usize;
+    type Error = ();
+    type Future = std::future::Ready<Result<usize, ()>>;
+
+    fn poll_ready(&mut self, _: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), ()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, data: usize) -> Self::Future {
+        std::future::ready(data.checked_sub(1).map(|_| data + 1).ok_or(()))
+    }
+}
+
+#[cfg(feature = "tower")]
+#[tokio::test]
+async fn test_tower_service() {
+    use std::task::Poll;
+    use tower_service::Service;
+    let (mut reqx, respx) = channel::new_bounded_bidirectional_async::<usize, usize>(1);
+    let mut reqy = reqx.clone();
+    let server = channel::serve_service(respx, AddOne);
+    poll_fn(|cx| reqx.poll_ready(cx)).await.unwrap();
+    let ready = poll_fn(|cx| Poll::Ready(reqy.poll_ready(cx))).await;
+    assert!(ready.is_pending());
+    let response = reqx.call(12);
+    assert_eq!(response.await, Ok(13));
+    poll_fn(|cx| reqy.poll_ready(cx)).await.unwrap();
+    assert_eq!(reqy.call(0).await, Err(channel::BDError::NoResponse));
+    server.shutdown().await;
+    let ready = poll_fn(|cx| reqx.poll_ready(cx)).await;
+    assert_eq!(ready, Err(channel::BDError::Disconnected));
+
+    // a call in flight does not keep the responders connected
+    let (mut reqx, mut respx) = channel::new_unbounded_bidirectional_async::<usize, usize>();
+    poll_fn(|cx| reqx.poll_ready(cx)).await.unwrap();
+    let response = reqx.call(12);
+    drop(reqx);
+    let (data, handle) = respx.take_request().await.unwrap();
+    let timeout = std::time::Duration::from_millis(100);
+    let next = tokio::time::timeout(timeout, respx.take_request()).await;
+    assert_eq!(next.unwrap().err(), Some(channel::BDError::Disconnected));
+    handle.reply(data + 1);
+    assert_eq!(response.await, Ok(13));
+}
+
+#[tokio::test]
+async fn test_new_unbounded_bidirectional_async_priority() {
+    let (reqx, mut respx) = channel::new_unbounded_bidirectional_async::<usize, usize>();
+    let now = std::time::Instant::now();
+    let mut requests = vec![];
+    for (data, priority, deadline) in [(12, 0, None), (15, 5, None), (18, 9, Some(now))] {
+        let mut reqx = reqx.clone();
+        requests.push(tokio::spawn(async move {
+            reqx.request_with(data, priority, deadline).await
+        }));
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    let mut taken = vec![];
+    for _ in 0..2 {
+        let (data, handle) = respx.take_request().await.unwrap();
+        taken.push(data);
+        handle.reply(data + 1);
+    }
+    assert_eq!(taken, vec![15, 12]);
+    let mut responses = vec![];
+    for request in requests {
+        responses.push(request.await.unwrap());
+    }
+    assert_eq!(
+        responses,
+        vec![Ok(13), Ok(16), Err(channel::BDError::DeadlineExceeded)]
+    );
+    assert!(respx.try_take_request().await.is_none());
+
+    // the deadline also ends the wait while no responder takes the request
+    let mut reqx = reqx.clone();
+    let deadline = std::time::Instant::now() + std::time::Duration::from_millis(10);
+    assert_eq!(
+        reqx.request_with(21, 0, Some(deadline)).await,
+        Err(channel::BDError::DeadlineExceeded)
+    );
+    assert_eq!(reqx.queue_len(), 1);
+    assert!(respx.try_take_request().await.is_none());
+}
+
+#[tokio::test]
+async fn test_new_unbounded_bidirectional_async_stream() {
+    let (mut reqx, mut respx) = channel::new_unbounded_bidirectional_async();
+    let mut respy = respx.clone();
+    let mut stream = reqx.request_stream(3).await.unwrap();
+    let responder = tokio::spawn(async move {
+        let (count, mut handle) = respx.take_request().await.unwrap();
+        for page in 0..count {
+            handle.send(page * 10);
+        }
+        handle.finish();
+        let (_, mut handle) = respx.take_request().await.unwrap();
+        handle.send(0);
+        handle.cancelled().await;
+    });
+    let mut pages = vec![];
+    while let Some(page) = stream.next().await {
+        pages.push(page);
+    }
+    assert_eq!(pages, vec![0, 10, 20]);
+    let mut stream = reqx.request_stream(1).await.unwrap();
+    assert_eq!(stream.next().await, Some(0));
+    drop(stream);
+    responder.await.unwrap();
+
+    // the stream also works through the futures Stream trait
+    let mut stream = reqx.request_stream(2).await.unwrap();
+    let (_, mut handle) = respy.take_request().await.unwrap();
+    handle.send(7);
+    handle.finish();
+    let mut pages = vec![];
+    while let Some(page) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+        pages.push(page);
+    }
+    assert_eq!(pages, vec![7]);
+}
+
+#[tokio::test]
+async fn test_new_async() {
+    let (tx, rx) = channel::new_async(None, false);
+    tx.send_items(vec![1, 2, 3]);
+    assert_eq!(rx.len(), 3);
+    let rx2 = rx.clone();
+    assert_eq!(rx.recv().await.unwrap(), 1);
+    assert_eq!(rx2.recv().await.unwrap(), 2);
+    assert_eq!(rx2.try_recv(), Some(3));
+    assert_eq!(rx.try_recv(), None);
+    let waiting = tokio::spawn(async move { rx2.recv().await.unwrap() });
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    tx.send(4);
+    assert_eq!(waiting.await.unwrap(), 4);
+    assert_eq!(
+        rx.recv_timeout(std::time::Duration::from_millis(10)).await,
+        Err(channel::RecvError::Timeout)
+    );
+
+    let (tx, rx) = channel::new_async(Some(2), false);
+    tx.send_items(vec![1, 2, 3]);
+    assert_eq!(rx.len(), 2);
+    assert_eq!(rx.recv().await.unwrap(), 2);
+    let sending = tokio::spawn(async move {
+        tx.send_wait(4).await;
+        tx.send_wait(5).await;
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    assert!(!sending.is_finished());
+    assert_eq!(rx.recv().await.unwrap(), 3);
+    sending.await.unwrap();
+    assert_eq!(rx.recv().await.unwrap(), 4);
+    assert_eq!(rx.recv().await.unwrap(), 5);
+}
+
+#[tokio::test]
+async fn test_new_async_dispatch() {
+    let (tx, rx) = channel::new_async(None, true);
+    let rx2 = rx.clone();
+    let waiting = [rx, rx2].map(|rx| tokio::spawn(async move { (rx.recv().await.unwrap(), rx) }));
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    tx.send(1);
+    let mut receivers = vec![];
+    for waiting in waiting {
+        let (data, rx) = waiting.await.unwrap();
+        assert_eq!(data, 1);
+        receivers.push(rx);
+    }
+
+    let (tx, rx) = channel::new_async(Some(2), true);
+    let rx2 = rx.clone();
+    tx.send_items(vec![1, 2]);
+    assert_eq!(rx.recv().await.unwrap(), 1);
+    assert_eq!(rx.recv().await.unwrap(), 2);
+    let sending = tokio::spawn(async move { tx.send_wait(3).await });
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    assert!(!sending.is_finished());
+    assert_eq!(rx2.recv().await.unwrap(), 1);
+    sending.await.unwrap();
+    assert_eq!(rx2.recv().await.unwrap(), 2);
+    assert_eq!(rx2.recv().await.unwrap(), 3);
+    assert_eq!(rx.recv().await.unwrap(), 3);
+
+    // a clone also receives the items every other receiver has not received yet
+    let (tx, rx) = channel::new_async(None, true);
+    tx.send_items(vec![1, 2]);
+    assert_eq!(rx.recv().await.unwrap(), 1);
+    let rx2 = rx.clone();
+    tx.send(3);
+    assert_eq!(rx2.len(), 2);
+    assert_eq!(rx2.recv().await.unwrap(), 2);
+    assert_eq!(rx2.recv().await.unwrap(), 3);
+    assert_eq!(rx.recv().await.unwrap(), 2);
+}
+
+#[tokio::test]
+async fn test_new_unbounded_async() {
+    let (tx, rx) = channel::new_unbounded_async();
+    assert!(rx.is_empty());
+    assert_eq!(rx.try_recv(), None);
+    for i in 1..=5 {
+        tx.send(i);
+    }
+    assert_eq!(rx.len(), 5);
+    assert_eq!(rx.recv().await.unwrap(), 1);
+    assert_eq!(rx.try_recv(), Some(2));
+    assert_eq!(rx.recv_many(2).await.unwrap(), vec![3, 4]);
+    assert_eq!(rx.recv_many(10).await.unwrap(), vec![5]);
+    assert_eq!(rx.recv_many(0).await.unwrap(), Vec::<i32>::new());
+    assert!(rx.is_empty());
+}
+
+#[tokio::test]
+async fn test_new_unbounded_async_timeout() {
+    let (tx, rx) = channel::new_unbounded_async();
+    let timeout = std::time::Duration::from_millis(10);
+    assert_eq!(
+        rx.recv_timeout(timeout).await,
+        Err(channel::RecvError::Timeout)
+    );
+    assert_eq!(
+        rx.recv_timeout(timeout).await,
+        Err(channel::RecvError::Timeout)
+    );
+    tx.send(1);
+    assert_eq!(rx.recv_timeout(timeout).await, Ok(1));
+    let sending = tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        tx.send(2);
+    });
+    assert_eq!(
+        rx.recv_timeout(std::time::Duration::from_secs(1)).await,
+        Ok(2)
+    );
+    sending.await.unwrap();
+    assert_eq!(
+        rx.recv_timeout(timeout).await,
+        Err(channel::RecvError::Disconnected)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_new_unbounded_async_concurrent() {
+    let (tx, rx) = channel::new_unbounded_async();
+    let receivers = (0..4)
+        .map(|_| {
+            let rx = rx.clone();
+            tokio::spawn(async move {
+                let mut received = vec![];
+                while let Ok(data) = rx.recv().await {
+                    received.push(data);
+                }
+                received
+            })
+        })
+        .collect::<Vec<_>>();
+    let senders = (0..4)
+        .map(|sender| {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                for i in 0..100 {
+                    tx.send(sender * 100 + i);
+                    tokio::task::yield_now().await;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    drop(tx);
+    for sender in senders {
+        sender.await.unwrap();
+    }
+    let mut received = vec![];
+    for receiver in receivers {
+        received.extend(receiver.await.unwrap());
+    }
+    received.sort();
+    assert_eq!(received, (0..400).collect::<Vec<_>>());
+    assert!(rx.is_empty());
+}
+
+#[tokio::test]
+async fn test_async_disconnected() {
+    let (tx, rx) = channel::new_unbounded_async();
+    let tx2 = tx.clone();
+    tx.send(1);
+    let waiting = tokio::spawn(async move { (rx.recv().await, rx.recv().await) });
+    drop(tx);
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    assert!(!waiting.is_finished());
+    drop(tx2);
+    assert_eq!(
+        waiting.await.unwrap(),
+        (Ok(1), Err(channel::RecvError::Disconnected))
+    );
+
+    let (tx, rx) = channel::new_async(Some(2), true);
+    let rx2 = rx.clone();
+    tx.send(1);
+    let waiting = tokio::spawn(async move { (rx.recv().await, rx.recv().await) });
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    drop(tx);
+    assert_eq!(
+        waiting.await.unwrap(),
+        (Ok(1), Err(channel::RecvError::Disconnected))
+    );
+    assert_eq!(rx2.recv().await, Ok(1));
+
+    let (reqx, mut respx) = channel::new_unbounded_bidirectional_async::<i32, i32>();
+    let mut respy = respx.clone();
+    let waiting = tokio::spawn(async move { respy.take_request().await.err() });
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    drop(reqx);
+    assert_eq!(waiting.await.unwrap(), Some(channel::BDError::Disconnected));
+    assert!(respx.take_request().await.is_err());
+}
+
+#[test]
+fn test_channel_reclaimed() {
+    let item = Arc::new(0);
+    let (tx, rx) = channel::new_unbounded_async();
+    tx.send(item.clone());
+    drop((tx, rx));
+    assert_eq!(Arc::strong_count(&item), 1);
+
+    let (tx, rx) = channel::new_async(Some(2), true);
+    let rx2 = rx.clone();
+    tx.send(item.clone());
+    drop((tx, rx, rx2));
+    assert_eq!(Arc::strong_count(&item), 1);
+
+    let (mut reqx, respx) = channel::new_unbounded_bidirectional::<Arc<i32>, i32>();
+    let ticket = reqx.send_request(item.clone());
+    drop(respx);
+    assert_eq!(Arc::strong_count(&item), 1);
+    assert_eq!(
+        reqx.try_get_response_for(ticket),
+        Some(Err(channel::BDError::Disconnected))
+    );
+
+    let (mut reqx, respx) = channel::new_unbounded_bidirectional_async::<Arc<i32>, i32>();
+    let requesting = reqx.request(item.clone());
+    drop(requesting);
+    drop((reqx, respx));
+    assert_eq!(Arc::strong_count(&item), 1);
+}
+
+#[test]
+fn test_channel_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<channel::UnboundedSenderAsync<i32>>();
+    assert_send_sync::<channel::UnboundedReceiverAsync<i32>>();
+    assert_send_sync::<channel::SenderAsync<i32>>();
+    assert_send_sync::<channel::ReceiverAsync<i32>>();
+    assert_send_sync::<channel::BDUnbRequester<i32, i32>>();
+    assert_send_sync::<channel::BDUnbResponder<i32, i32>>();
+    assert_send_sync::<channel::BDUnbRequesterAsync<i32, i32>>();
+    assert_send_sync::<channel::BDUnbResponderAsync<i32, i32>>();
+    assert_send_sync::<channel::TSSender<MyTSStruct>>();
+    assert_send_sync::<channel::TSReceiver<MyTSStruct>>();
+    fn assert_send<T: Send>() {}
+    assert_send::<channel::TSMerge<MyTSStruct>>();
+    assert_send::<channel::TSWindow<MyTSStruct, Vec<MyTSStruct>>>();
+    assert_send::<channel::TSResampler<MyTSStruct, MyTSStruct>>();
+}
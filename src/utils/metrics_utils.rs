@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::panic::Location;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub enum HolderType {
@@ -12,6 +13,8 @@ pub struct MetricsManager {
     caller_locs: Vec<String>,
     caller_holder_types: Vec<HolderType>,
     index_metrics: HashMap<usize, usize>,
+    index_dropped: HashMap<usize, usize>,
+    index_gaps: HashMap<usize, usize>,
 }
 
 impl MetricsManager {
@@ -20,6 +23,8 @@ impl MetricsManager {
             caller_locs: vec![],
             caller_holder_types: vec![],
             index_metrics: HashMap::new(),
+            index_dropped: HashMap::new(),
+            index_gaps: HashMap::new(),
         }
     }
 
@@ -40,6 +45,14 @@ impl MetricsManager {
         *self.index_metrics.entry(index).or_insert(0) += count;
     }
 
+    pub fn record_dropped(&mut self, index: usize, count: usize) {
+        *self.index_dropped.entry(index).or_insert(0) += count;
+    }
+
+    pub fn record_gap(&mut self, index: usize, count: usize) {
+        *self.index_gaps.entry(index).or_insert(0) += count;
+    }
+
     pub fn get_result(&mut self, clear: bool) -> MetricsResult {
         let mut sender_counts = HashMap::new();
         let mut receiver_counts = HashMap::new();
@@ -56,12 +69,101 @@ impl MetricsManager {
         MetricsResult {
             sender_counts,
             receiver_counts,
+            dropped_counts: Self::get_index_result(
+                &self.caller_locs,
+                &mut self.index_dropped,
+                clear,
+            ),
+            gap_counts: Self::get_index_result(&self.caller_locs, &mut self.index_gaps, clear),
         }
     }
+
+    fn get_index_result(
+        caller_locs: &[String],
+        index_counts: &mut HashMap<usize, usize>,
+        clear: bool,
+    ) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for (index, value) in index_counts.iter_mut() {
+            counts.insert(caller_locs[*index].clone(), *value);
+            if clear {
+                *value = 0;
+            }
+        }
+        counts
+    }
 }
 
 #[derive(Debug)]
 pub struct MetricsResult {
     pub sender_counts: HashMap<String, usize>,
     pub receiver_counts: HashMap<String, usize>,
+    pub dropped_counts: HashMap<String, usize>,
+    pub gap_counts: HashMap<String, usize>,
+}
+
+/// The latency of one request handler, such as the one run by `serve`
+#[derive(Debug)]
+pub struct LatencyMetrics {
+    caller_loc: String,
+    handled: usize,
+    panicked: usize,
+    total_latency: Duration,
+    max_latency: Duration,
+}
+
+impl LatencyMetrics {
+    pub fn new(caller: &'static Location<'static>) -> Self {
+        Self {
+            caller_loc: format!("{}:{}", caller.file(), caller.line()),
+            handled: 0,
+            panicked: 0,
+            total_latency: Duration::ZERO,
+            max_latency: Duration::ZERO,
+        }
+    }
+
+    pub fn record(&mut self, latency: Duration, panicked: bool) {
+        self.handled += 1;
+        if panicked {
+            self.panicked += 1;
+        }
+        self.total_latency += latency;
+        self.max_latency = self.max_latency.max(latency);
+    }
+
+    pub fn get_result(&mut self, clear: bool) -> LatencyResult {
+        let result = LatencyResult {
+            caller: self.caller_loc.clone(),
+            handled: self.handled,
+            panicked: self.panicked,
+            total_latency: self.total_latency,
+            max_latency: self.max_latency,
+        };
+        if clear {
+            self.handled = 0;
+            self.panicked = 0;
+            self.total_latency = Duration::ZERO;
+            self.max_latency = Duration::ZERO;
+        }
+        result
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LatencyResult {
+    pub caller: String,
+    pub handled: usize,
+    pub panicked: usize,
+    pub total_latency: Duration,
+    pub max_latency: Duration,
+}
+
+impl LatencyResult {
+    pub fn mean_latency(&self) -> Option<Duration> {
+        match self.handled {
+            0 => None,
+            handled => Some(self.total_latency.div_f64(handled as f64)),
+        }
+    }
 }
@@ -1,4 +1,4 @@
-use chrono::NaiveDateTime;
+use chrono::{Duration, NaiveDateTime};
 
 pub trait NaiveDateTimeExt {
     fn now() -> Self;
@@ -9,3 +9,14 @@ impl NaiveDateTimeExt for NaiveDateTime {
         chrono::Utc::now().naive_local()
     }
 }
+
+/// Round the data time down to a multiple of the step since `NaiveDateTime::default()`
+pub(crate) fn align_data_time(data_time: NaiveDateTime, step: Duration) -> NaiveDateTime {
+    let nanos = |duration: Duration| {
+        duration.num_seconds() as i128 * 1_000_000_000 + duration.subsec_nanos() as i128
+    };
+    let rem = nanos(data_time - NaiveDateTime::default()).rem_euclid(nanos(step));
+    data_time
+        - Duration::seconds((rem / 1_000_000_000) as i64)
+        - Duration::nanoseconds((rem % 1_000_000_000) as i64)
+}
@@ -1,5 +1,6 @@
 pub trait VecExt<T: Clone> {
     fn query_items(&self, start: usize, end: Option<usize>) -> Vec<T>;
+    fn count_front_while<F: Fn(&T) -> bool>(&self, f: F) -> usize;
 }
 
 impl<T: Clone> VecExt<T> for Vec<T> {
@@ -11,4 +12,8 @@ impl<T: Clone> VecExt<T> for Vec<T> {
             self[start..end].to_vec()
         }
     }
+
+    fn count_front_while<F: Fn(&T) -> bool>(&self, f: F) -> usize {
+        self.iter().take_while(|item| f(item)).count()
+    }
 }